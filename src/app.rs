@@ -1,6 +1,22 @@
-use super::dit_core::{read_state, validate, with_game_state, ActionInterface};
+use super::dit_core::{
+    create_book, create_identity, expected_attempts, read_book, read_book_trusted, read_identity,
+    read_mode, read_or_new_book, replace_book, validate, validate_forensic, write_book, Action,
+    ActionInterface, Book, CancellationToken, Error, Estimate, ForensicReport, Identity,
+    LinkFailure, LinkReport, Mode, ModeRegistry, ModeVisitor, NonceSource, SignatureCheck, State,
+};
 use super::mode_a::ActionA;
 use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long to measure the hash rate for before mining, to show an ETA.
+const BENCHMARK_TIME: Duration = Duration::from_millis(200);
+
+/// How many attempts go by between progress updates while mining.
+const PROGRESS_PERIOD: u32 = 1 << 20;
 
 pub fn get_app<'a, 'b>() -> App<'a, 'b> {
     App::new("dit")
@@ -15,6 +31,14 @@ pub fn get_app<'a, 'b>() -> App<'a, 'b> {
                 .help("Sets a config file")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .value_name("MODE")
+                .help("The mode for new files. Defaults to A")
+                .takes_value(true),
+        )
         .subcommand(
             SubCommand::with_name("test")
                 .about("controls testing features")
@@ -29,55 +53,578 @@ pub fn get_app<'a, 'b>() -> App<'a, 'b> {
         .subcommand(subcommand_raw_add())
         .subcommand(subcommand_update())
         .subcommand(subcommand_validate())
+        .subcommand(subcommand_undo())
+        .subcommand(subcommand_fork())
+        .subcommand(subcommand_rebase())
+        .subcommand(subcommand_diff())
+        .subcommand(subcommand_merge_driver())
+        .subcommand(subcommand_keygen())
 }
 
 // Should later change it to have its own Writer
 pub fn handle_matches(app_m: ArgMatches) {
     let config_arg = app_m.value_of("config");
     match app_m.subcommand() {
-        ("update", Some(arg_m)) => {
+        ("test", Some(arg_m)) => {
+            println!("{:?} {:?}", config_arg, arg_m.is_present("debug"));
+        }
+        (name, Some(arg_m)) => {
+            let mut registry = ModeRegistry::new();
+            registry.register::<ActionA>();
+            let modes = registry.modes();
+            let new_file_mode = app_m
+                .value_of("mode")
+                .and_then(|mode| mode.chars().next())
+                .map_or(modes[0], Mode::new);
+            let mode = match mode_file_name(name, arg_m) {
+                // Files that don't say which mode they are were written
+                // before there were any other modes
+                Some(file_name) if !is_new_file(file_name) => {
+                    read_mode(file_name).unwrap_or(Mode::A)
+                }
+                _ => new_file_mode,
+            };
+            // Unknown modes go to the default mode, which reports them
+            let known_mode = if registry.contains(mode) {
+                mode
+            } else {
+                modes[0]
+            };
+            let command = Command {
+                name,
+                arg_m,
+                mode,
+                modes,
+            };
+            registry.dispatch(known_mode, command);
+        }
+        _ => {
+            println!("TODO We should probably print available subcommands in this case. Perhaps with help of the man crate?");
+        }
+    }
+}
+
+/// The file a subcommand reads the mode from, or `None` for subcommands that
+/// don't work on a book.
+fn mode_file_name<'a>(name: &str, arg_m: &'a ArgMatches) -> Option<&'a str> {
+    let arg = match name {
+        "keygen" => return None,
+        "diff" => "left",
+        "merge-driver" => "ours",
+        _ => "filename",
+    };
+    Some(arg_m.value_of(arg).unwrap_or(".dit"))
+}
+
+/// Whether there is no book in `file_name` yet, because the file doesn't
+/// exist or is empty.
+fn is_new_file(file_name: &str) -> bool {
+    !fs::metadata(file_name).is_ok_and(|metadata| metadata.len() > 0)
+}
+
+/// A subcommand, run with whichever action implements the mode of its file.
+struct Command<'a, 'b> {
+    name: &'a str,
+    arg_m: &'a ArgMatches<'b>,
+    mode: Mode,
+    modes: Vec<Mode>,
+}
+
+impl ModeVisitor for Command<'_, '_> {
+    type Output = ();
+
+    fn visit<A: Action>(self) {
+        let Command {
+            name,
+            arg_m,
+            mode,
+            modes,
+        } = self;
+        let result = if mode == A::State::mode() {
+            run_command::<A>(name, arg_m)
+        } else {
+            Err(Error::WrongMode {
+                mode,
+                expected_modes: Vec::new(),
+            })
+        };
+        let result = result.map_err(|err| match err {
+            Error::WrongMode { mode, .. } => Error::WrongMode {
+                mode,
+                expected_modes: modes,
+            },
+            err => err,
+        });
+        match result {
+            Err(err) if name == "merge-driver" => {
+                eprintln!("{}", err);
+                // Tells git there is a conflict
+                std::process::exit(1);
+            }
+            Err(err) if name == "validate" => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+            Err(err) => println!("{}", err),
+            Ok(()) => (),
+        }
+    }
+}
+
+fn run_command<A: Action>(name: &str, arg_m: &ArgMatches) -> Result<(), Error<A>> {
+    match name {
+        "update" => {
             let file_name: &str = arg_m.value_of("filename").unwrap_or(".dit");
             let version: usize = arg_m.value_of("version").unwrap_or("5").parse().unwrap();
-            let (state, ledger) = read_state::<ActionA>(file_name).unwrap();
-
-            ActionInterface::new()
-                .with_period(1)
-                .on_iter(|hex_string| println!("-> {}", hex_string.to_string()))
+            let action =
+                match action_from_json(json!({"type": "updateversion", "version": version})) {
+                    Some(action) => action,
+                    None => return Ok(()),
+                };
+            let identity = player_identity(arg_m)?;
+            let mut action_interface = mining_interface(arg_m);
+            action_interface
+                .with_period(PROGRESS_PERIOD)
+                .on_progress(|stats| println!("... {}", stats))
                 .on_fail(|| println!("Oooooh, we failed"))
-                .on_success(|hex_string| println!("-> {} wins!", hex_string.to_string()))
-                .run(ActionA::UpdateVersion { version }, ledger, state)
-                .unwrap();
+                .on_success(|hex_string| println!("-> {} wins!", hex_string));
+            append_to_file(file_name, action, action_interface, identity)
         }
-        ("test", Some(arg_m)) => {
-            println!("{:?} {:?}", config_arg, arg_m.is_present("debug"));
-        }
-        ("rawadd", Some(arg_m)) => {
+        "rawadd" => {
             let file_name: &str = arg_m.value_of("filename").unwrap_or(".dit");
             let message_payload: &str = arg_m.value_of("content").unwrap();
-            let k = with_game_state(file_name, |_| {
-                Ok(ActionA::Marker {
-                    content: String::from(message_payload),
-                })
-            });
-            k.unwrap();
-        }
-        ("validate", Some(arg_m)) => {
+            let action =
+                match action_from_json(json!({"type": "marker", "content": message_payload})) {
+                    Some(action) => action,
+                    None => return Ok(()),
+                };
+            let identity = player_identity(arg_m)?;
+            let mut action_interface = mining_interface(arg_m);
+            action_interface
+                .with_period(PROGRESS_PERIOD)
+                .on_progress(|stats| println!("... {}", stats));
+            append_to_file(file_name, action, action_interface, identity)
+        }
+        "undo" => {
+            let file_name: &str = arg_m.value_of("filename").unwrap_or(".dit");
+            let keep = |len: usize| match (arg_m.value_of("to"), arg_m.value_of("count")) {
+                (Some(to), _) => to.parse().unwrap(),
+                (None, count) => len.saturating_sub(count.unwrap_or("1").parse().unwrap()),
+            };
+            undo_in_file::<A, _>(file_name, keep, arg_m.is_present("dry-run"))
+        }
+        "fork" => {
+            let file_name: &str = arg_m.value_of("filename").unwrap();
+            let fork_name: &str = arg_m.value_of("fork").unwrap();
+            let at: Option<usize> = arg_m.value_of("at").map(|at| at.parse().unwrap());
+            fork_file::<A>(file_name, fork_name, at)
+        }
+        "rebase" => {
+            let file_name: &str = arg_m.value_of("filename").unwrap();
+            let onto_name: &str = arg_m.value_of("onto").unwrap();
+            let mut action_interface = mining_interface(arg_m);
+            if let Some(identity) = player_identity(arg_m)? {
+                action_interface.with_identity(identity);
+            }
+            rebase_file::<A>(file_name, onto_name, &action_interface)
+        }
+        "diff" => {
+            let left_name: &str = arg_m.value_of("left").unwrap();
+            let right_name: &str = arg_m.value_of("right").unwrap();
+            diff_files::<A>(left_name, right_name)
+        }
+        "merge-driver" => {
+            let ancestor_name: &str = arg_m.value_of("ancestor").unwrap();
+            let ours_name: &str = arg_m.value_of("ours").unwrap();
+            let theirs_name: &str = arg_m.value_of("theirs").unwrap();
+            let mut action_interface = mining_interface(arg_m);
+            if let Some(identity) = player_identity(arg_m)? {
+                action_interface.with_identity(identity);
+            }
+            merge_files::<A>(ancestor_name, ours_name, theirs_name, &action_interface)
+        }
+        "validate" => {
             let file_name: &str = arg_m.value_of("filename").unwrap_or("dummy");
-            let validation_result = validate::<ActionA>(file_name);
-            match validation_result {
-                Ok(_) => println!("I would consider {} as valid", file_name),
-                Err(err) => println!("{}", err),
+            let require_signatures = arg_m.is_present("require-signatures");
+            if arg_m.is_present("all") {
+                let report = validate_forensic::<A>(file_name)?;
+                return print_forensic_report(file_name, &report, require_signatures);
+            }
+            let report = validate::<A>(file_name)?;
+            match report.first_unsigned() {
+                Some(link) if require_signatures => Err(Error::Unsigned {
+                    file_name: String::from(file_name),
+                    line_number: link.line_number(),
+                }),
+                _ => {
+                    println!(
+                        "I would consider {} as valid, with {} messages",
+                        file_name,
+                        report.links().len()
+                    );
+                    Ok(())
+                }
             }
         }
+        "keygen" => {
+            let file_name = match identity_file_name(arg_m) {
+                Some(file_name) => file_name,
+                None => {
+                    println!("I don't know where your home directory is, so please pick a file with --identity");
+                    return Ok(());
+                }
+            };
+            let identity = create_identity::<A>(&file_name)?;
+            println!(
+                "Your new identity is saved in {}. Keep it secret!\nYour public key is {}",
+                file_name,
+                identity.public_key()
+            );
+            Ok(())
+        }
         _ => {
             println!("TODO We should probably print available subcommands in this case. Perhaps with help of the man crate?");
+            Ok(())
+        }
+    }
+}
+
+/// Builds an action for the mode `A` is for, if it has an action like `json`.
+fn action_from_json<A: Action>(json: serde_json::Value) -> Option<A> {
+    let action_type = json["type"].as_str().map(String::from);
+    match serde_json::from_value(json) {
+        Ok(action) => Some(action),
+        Err(_) => {
+            println!(
+                "Sorry, mode {} doesn't have {} actions",
+                A::State::mode(),
+                action_type.unwrap_or_default()
+            );
+            None
+        }
+    }
+}
+
+/// Prints every problem in `report`, failing if there were any. With
+/// `require_signatures`, unsigned messages count as problems too.
+fn print_forensic_report<A: Action>(
+    file_name: &str,
+    report: &ForensicReport<A>,
+    require_signatures: bool,
+) -> Result<(), Error<A>> {
+    let unsigned: Vec<&LinkReport> = report
+        .links()
+        .iter()
+        .filter(|link| require_signatures && link.signature() == SignatureCheck::Unsigned)
+        .collect();
+    if report.is_valid() && unsigned.is_empty() {
+        println!(
+            "I checked all {} messages in {}, and they all look fine",
+            report.links().len(),
+            file_name
+        );
+        return Ok(());
+    }
+    println!(
+        "I checked all {} messages in {}, and found these problems:",
+        report.links().len(),
+        file_name
+    );
+    for span in report.spans() {
+        let problem = match span.failure() {
+            LinkFailure::Hash => "not linked to the message before",
+            LinkFailure::Applicable => "action can't be applied",
+            LinkFailure::Apply => "action failed when applied",
+            LinkFailure::Signature => "signed by someone other than the player whose book it is",
+        };
+        if span.message_count() == 1 {
+            println!("  line {}: {}", span.first().line_number(), problem);
+        } else {
+            println!(
+                "  lines {}-{}: {} ({} messages)",
+                span.first().line_number(),
+                span.last().line_number(),
+                problem,
+                span.message_count()
+            );
+        }
+    }
+    for link in unsigned.iter() {
+        println!("  line {}: not signed", link.line_number());
+    }
+    Err(Error::ProblemsFound {
+        file_name: String::from(file_name),
+        problem_count: report.spans().len() + unsigned.len(),
+    })
+}
+
+/// Removes messages from the end of the book in `file_name`, keeping as many
+/// as `keep` returns when given the number of messages in the book.
+///
+/// If the book has a broken link, everything from the first broken link on is
+/// removed too, so that the book is valid again.
+fn undo_in_file<A, F>(file_name: &str, keep: F, dry_run: bool) -> Result<(), Error<A>>
+where
+    A: Action,
+    F: FnOnce(usize) -> usize,
+{
+    let mut book = read_book_trusted::<A>(file_name)?;
+    let bit_costs = book.bit_costs().to_vec();
+    let linked_len = book.linked_len()?;
+    let keep = keep(bit_costs.len()).min(bit_costs.len());
+    // Only a break before the messages being kept changes what is undone
+    if linked_len < book.messages().len() && linked_len < keep {
+        println!(
+            "Message #{} in {} is broken, so everything from there on goes too",
+            linked_len + 1,
+            file_name
+        );
+    }
+    let keep = keep.min(linked_len);
+    let discarded = book.truncate_to(keep)?;
+    if discarded.is_empty() {
+        println!("There's nothing to undo in {}", file_name);
+        return Ok(());
+    }
+    println!(
+        "{} {} messages from {}:",
+        if dry_run { "I would undo" } else { "Undoing" },
+        discarded.len(),
+        file_name
+    );
+    let mut attempts = 0.0;
+    for (message, index) in discarded.iter().zip(keep..) {
+        let bit_cost = bit_costs[index];
+        println!(
+            "  #{} {} ({} bits)",
+            index + 1,
+            message.action().to_string(),
+            bit_cost
+        );
+        attempts += expected_attempts(bit_cost);
+    }
+    println!(
+        "That's about {:.0} attempts worth of work thrown away.",
+        attempts
+    );
+    if !dry_run {
+        write_book(file_name, &mut book)?;
+    }
+    Ok(())
+}
+
+/// Forks the book in `file_name` into `fork_name` after message number `at`,
+/// or after the last message if `at` isn't given.
+fn fork_file<A: Action>(
+    file_name: &str,
+    fork_name: &str,
+    at: Option<usize>,
+) -> Result<(), Error<A>> {
+    let book = read_book::<A>(file_name)?;
+    let message_count = book.ledger().messages().len();
+    let at = at.unwrap_or(message_count);
+    if at == 0 {
+        println!("A fork needs at least one message from {}", file_name);
+        return Ok(());
+    }
+    let mut fork = book.fork(at - 1)?;
+    create_book(fork_name, &mut fork)?;
+    println!(
+        "Forked {} into {} after message #{} of {}",
+        file_name, fork_name, at, message_count
+    );
+    Ok(())
+}
+
+/// Re-mines the actions in `file_name` that aren't in `onto_name` onto the
+/// end of `onto_name`, and saves the result over `file_name`.
+fn rebase_file<A: Action>(
+    file_name: &str,
+    onto_name: &str,
+    action_interface: &ActionInterface,
+) -> Result<(), Error<A>> {
+    let book = read_book::<A>(file_name)?;
+    let mut onto = read_book::<A>(onto_name)?;
+    let common = book.common_prefix(&onto);
+    let actions: Vec<A> = book.ledger().messages()[common..]
+        .iter()
+        .map(|message| message.action().clone())
+        .collect();
+    if actions.is_empty() {
+        println!(
+            "{} has nothing that isn't already in {}",
+            file_name, onto_name
+        );
+        return Ok(());
+    }
+    println!(
+        "{} and {} share {} messages. Re-mining {} actions...",
+        file_name,
+        onto_name,
+        common,
+        actions.len()
+    );
+    let report = onto.rebase(
+        actions,
+        action_interface.miner(),
+        action_interface.identity(),
+    )?;
+    for (index, err) in report.skipped() {
+        println!("  Dropped #{}. {}", common + index + 1, err);
+    }
+    replace_book(file_name, &mut onto)?;
+    println!(
+        "Rebased {} actions from {} onto {}",
+        report.rebased().len(),
+        file_name,
+        onto_name
+    );
+    Ok(())
+}
+
+/// Shows where the books in two files diverge. Links aren't checked, so this
+/// works on tampered files too.
+fn diff_files<A: Action>(left_name: &str, right_name: &str) -> Result<(), Error<A>> {
+    let left = read_book_trusted::<A>(left_name)?;
+    let right = read_book_trusted::<A>(right_name)?;
+    let diff = left.diff(&right);
+    if diff.is_same() {
+        println!("{} and {} are the same", left_name, right_name);
+        return Ok(());
+    }
+    match diff.last_shared() {
+        Some(message) => println!(
+            "{} and {} share {} messages, up to {}",
+            left_name,
+            right_name,
+            diff.shared(),
+            message.key()
+        ),
+        None => println!("{} and {} have nothing in common", left_name, right_name),
+    }
+    for (file_name, messages, state) in [
+        (left_name, diff.left_only(), diff.left_state()),
+        (right_name, diff.right_only(), diff.right_state()),
+    ]
+    .iter()
+    {
+        println!("\nOnly in {}:", file_name);
+        for (message, index) in messages.iter().zip(diff.shared()..) {
+            println!(
+                "  #{} {} {}",
+                index + 1,
+                message.key(),
+                message.action().to_string()
+            );
+        }
+        println!("{} ends with {:#?}", file_name, state);
+    }
+    Ok(())
+}
+
+/// Merges the book in `theirs_name` into the book in `ours_name`, saving the
+/// result in `ours_name` as git expects of a merge driver.
+fn merge_files<A: Action>(
+    ancestor_name: &str,
+    ours_name: &str,
+    theirs_name: &str,
+    action_interface: &ActionInterface,
+) -> Result<(), Error<A>> {
+    let ancestor = read_book::<A>(ancestor_name)?;
+    let mut ours = read_book::<A>(ours_name)?;
+    let theirs = read_book::<A>(theirs_name)?;
+    let report = ours.merge(
+        &ancestor,
+        &theirs,
+        action_interface.miner(),
+        action_interface.identity(),
+    )?;
+    write_book(ours_name, &mut ours)?;
+    eprintln!("Merged by re-mining {} actions", report.rebased().len());
+    Ok(())
+}
+
+/// Opens the book in `file_name`, appends `action` to it and saves it.
+///
+/// New books belong to the player with `identity`, if there is one, and
+/// messages are signed in any book that belongs to a player. Books that don't
+/// belong to anyone are left unsigned.
+fn append_to_file<A: Action>(
+    file_name: &str,
+    action: A,
+    mut action_interface: ActionInterface,
+    identity: Option<Identity>,
+) -> Result<(), Error<A>> {
+    let mut book = match &identity {
+        Some(identity) if is_new_file(file_name) => Book::new_signed(identity),
+        _ => read_or_new_book(file_name)?,
+    };
+    if let Some(identity) = identity {
+        if book.header().public_key().is_some() {
+            action_interface.with_identity(identity);
         }
     }
+    if book.applicable(&action).is_ok() {
+        let hashes_per_second = action_interface
+            .miner()
+            .benchmark::<A>(&book.header().hash_algorithm()?, BENCHMARK_TIME);
+        println!(
+            "{}",
+            Estimate::new(book.bit_cost(&action), hashes_per_second)
+        );
+    }
+    action_interface.run_on(action, &mut book)?;
+    write_book(file_name, &mut book)
+}
+
+/// Where the player's identity is kept: the `--identity` file, or
+/// `~/.dit_identity` by default.
+fn identity_file_name(arg_m: &ArgMatches) -> Option<String> {
+    match arg_m.value_of("identity") {
+        Some(file_name) => Some(String::from(file_name)),
+        None => std::env::var("HOME")
+            .ok()
+            .map(|home| format!("{}/.dit_identity", home)),
+    }
+}
+
+/// The player's identity, if they've made one with `dit keygen` or named one
+/// with `--identity`.
+fn player_identity<A: Action>(arg_m: &ArgMatches) -> Result<Option<Identity>, Error<A>> {
+    match identity_file_name(arg_m) {
+        Some(file_name) if arg_m.is_present("identity") || Path::new(&file_name).exists() => {
+            read_identity(&file_name).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Sets up an ActionInterface with the mining options shared by subcommands,
+/// which stops mining cleanly on Ctrl-C.
+fn mining_interface(arg_m: &ArgMatches) -> ActionInterface {
+    let mut action_interface = ActionInterface::new();
+    if let Some(threads) = arg_m.value_of("threads") {
+        action_interface.with_threads(threads.parse().unwrap());
+    }
+    if let Some(timeout) = arg_m.value_of("timeout") {
+        action_interface.with_timeout(Duration::from_secs(timeout.parse().unwrap()));
+    }
+    if let Some(max_attempts) = arg_m.value_of("max-attempts") {
+        action_interface.with_max_attempts(max_attempts.parse().unwrap());
+    }
+    if let Some(seed) = arg_m.value_of("seed") {
+        action_interface.with_nonce_source(NonceSource::Seeded(seed.parse().unwrap()));
+    }
+    let cancellation = CancellationToken::new();
+    let handler_token = cancellation.clone();
+    ctrlc::set_handler(move || handler_token.cancel()).expect("Could not set Ctrl-C handler");
+    action_interface.with_cancellation(cancellation);
+    action_interface
 }
 
 //| Subcommand to add a raw message to a file
 fn subcommand_raw_add<'a, 'b>() -> App<'a, 'b> {
-    return SubCommand::with_name("rawadd")
+    SubCommand::with_name("rawadd")
         .arg(
             Arg::with_name("filename")
                 .help("Sets the file to add message")
@@ -91,17 +638,165 @@ fn subcommand_raw_add<'a, 'b>() -> App<'a, 'b> {
                 .help("Select file to validate")
                 .required(true)
                 .index(1),
-        );
+        )
+        .arg(arg_identity())
+        .args(&args_mining())
 }
 
 //| Subcommand to validate a file.
 fn subcommand_validate<'a, 'b>() -> App<'a, 'b> {
-    return SubCommand::with_name("validate").arg(
-        Arg::with_name("filename")
-            .help("Select file to validate")
-            .index(1)
-            .required(true),
-    );
+    SubCommand::with_name("validate")
+        .arg(
+            Arg::with_name("filename")
+                .help("Select file to validate")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("all")
+                .help("Keep checking past broken links and report every problem")
+                .long("all")
+                .short("a"),
+        )
+        .arg(
+            Arg::with_name("require-signatures")
+                .help("Also check that every message is signed by the player whose book it is")
+                .long("require-signatures"),
+        )
+}
+
+//| Subcommand to make a key pair for signing messages.
+fn subcommand_keygen<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("keygen")
+        .about("Creates an identity to sign your messages with")
+        .arg(arg_identity())
+}
+
+//| Subcommand to take back messages from the end of a file.
+fn subcommand_undo<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("undo")
+        .about("Removes the last messages from a file")
+        .arg(
+            Arg::with_name("filename")
+                .help("Select file to undo messages in")
+                .index(1)
+                .required(false),
+        )
+        .arg(
+            Arg::with_name("to")
+                .help("Keep only the first N messages")
+                .long("to")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number::<usize>)
+                .conflicts_with("count"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .help("How many messages to remove from the end. Defaults to 1")
+                .long("count")
+                .short("n")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number::<usize>),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .help("Only show what would be removed")
+                .long("dry-run"),
+        )
+}
+
+//| Subcommand to branch a file off into a new one.
+fn subcommand_fork<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("fork")
+        .about("Copies the start of a file into a new file, to try a different path")
+        .arg(
+            Arg::with_name("filename")
+                .help("Select file to fork")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("fork")
+                .help("The new file to create")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("at")
+                .help("Fork after message number N. Defaults to the last message")
+                .long("at")
+                .takes_value(true)
+                .value_name("N")
+                .validator(is_number::<usize>),
+        )
+}
+
+//| Subcommand to move the actions of one file onto the end of another.
+fn subcommand_rebase<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("rebase")
+        .about("Re-mines the actions of a file that aren't in another file onto the end of it")
+        .arg(
+            Arg::with_name("filename")
+                .help("Select file to rebase. It is replaced with the result")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("onto")
+                .help("The file to rebase onto. It is left as it is")
+                .index(2)
+                .required(true),
+        )
+        .arg(arg_identity())
+        .args(&args_mining())
+}
+
+//| Subcommand to compare two files.
+fn subcommand_diff<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("diff")
+        .about("Shows where two files diverge, and what each does after that")
+        .arg(
+            Arg::with_name("left")
+                .help("The first file to compare")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("right")
+                .help("The second file to compare")
+                .index(2)
+                .required(true),
+        )
+}
+
+//| Subcommand for git to merge files with. Set it up with
+//|     git config merge.dit.driver "dit merge-driver %O %A %B"
+//| and a `*.dit merge=dit` line in .gitattributes
+fn subcommand_merge_driver<'a, 'b>() -> App<'a, 'b> {
+    SubCommand::with_name("merge-driver")
+        .about("Merges two copies of a file for git, re-mining their actions on top of ours")
+        .arg(
+            Arg::with_name("ancestor")
+                .help("The version both sides started from (%O)")
+                .index(1)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("ours")
+                .help("Our version, which is replaced with the merged result (%A)")
+                .index(2)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("theirs")
+                .help("Their version (%B)")
+                .index(3)
+                .required(true),
+        )
+        .arg(arg_identity())
+        .args(&args_mining())
 }
 
 fn subcommand_update<'a, 'b>() -> App<'a, 'b> {
@@ -120,4 +815,53 @@ fn subcommand_update<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .value_name("VERSION"),
         )
+        .arg(arg_identity())
+        .args(&args_mining())
+}
+
+/// Checks that an argument is a number that fits in a `T`, so that it can be
+/// parsed later without failing.
+fn is_number<T: FromStr>(value: String) -> Result<(), String> {
+    match value.parse::<T>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("\"{}\" isn't a number I can use here", value)),
+    }
+}
+
+fn arg_identity<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("identity")
+        .help("The file your identity is kept in. Defaults to ~/.dit_identity")
+        .long("identity")
+        .takes_value(true)
+        .value_name("FILE")
+}
+
+fn args_mining<'a, 'b>() -> [Arg<'a, 'b>; 4] {
+    [
+        Arg::with_name("threads")
+            .help("How many threads to mine with. Defaults to one for each core")
+            .long("threads")
+            .short("t")
+            .takes_value(true)
+            .value_name("THREADS")
+            .validator(is_number::<usize>),
+        Arg::with_name("timeout")
+            .help("Give up mining after this many seconds")
+            .long("timeout")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .validator(is_number::<u64>),
+        Arg::with_name("max-attempts")
+            .help("Give up mining after this many attempts")
+            .long("max-attempts")
+            .takes_value(true)
+            .value_name("ATTEMPTS")
+            .validator(is_number::<u64>),
+        Arg::with_name("seed")
+            .help("Try keys from a generator with this seed, so the same key is found every time")
+            .long("seed")
+            .takes_value(true)
+            .value_name("SEED")
+            .validator(is_number::<u64>),
+    ]
 }
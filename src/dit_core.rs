@@ -1,21 +1,38 @@
 //! This module is for the core of the `dit` game, which should not change even
 //! as the interface changes or has different interfaces.
 
+pub mod difficulty;
+mod hash;
 mod model;
 mod ops;
+mod registry;
 mod traits;
 mod work;
 
-pub use model::{ActionInterface, Error, HexString, Ledger, Message, Mode, PendingLedger};
-pub use ops::{read_state, validate, with_game_state};
+pub use hash::{HashAlgorithm, LinkHasher};
+pub use model::{
+    expected_attempts, ActionInterface, Book, BookDiff, CancellationToken, Diagnostic, Error,
+    Estimate, FailureSpan, ForensicReport, Header, HexString, Identity, Ledger, LedgerBuf,
+    LedgerRng, LinkFailure, LinkReport, Message, Miner, MiningStats, Mode, NonceSource,
+    PendingLedger, RandomSource, RebaseReport, SignatureCheck, StopReason, ValidationReport,
+};
+#[allow(deprecated)]
+pub use ops::{
+    create_book, create_identity, read_book, read_book_trusted, read_identity, read_mode,
+    read_or_new_book, read_state, replace_book, validate, validate_forensic,
+    validate_forensic_read, validate_read, with_game_state, write_book,
+};
+pub use registry::{ModeRegistry, ModeVisitor};
 pub use traits::{Action, State};
 
-use work::bit_match;
+use work::{bit_match, key_width, stretch_key};
 
 #[cfg(test)]
 mod test {
-    use super::HexString;
+    use super::super::mode_a::ActionA;
+    use super::{validate_read, HexString};
     use rand::{thread_rng, Rng};
+    use std::io::Cursor;
 
     #[test]
     fn hex_string_from_bytes_to_bytes_is_equal() {
@@ -23,7 +40,7 @@ mod test {
             let random_n = thread_rng().gen::<u32>();
             let hex_string = HexString::from(&random_n.to_le_bytes()[..]);
             let mut hex_bytes: [u8; 4] = [0; 4];
-            for (i, byte) in (0..).zip(hex_string.to_bytes().into_iter()) {
+            for (i, byte) in (0..).zip(hex_string.to_bytes()) {
                 hex_bytes[i] = byte;
             }
             let converted = u32::from_le_bytes(hex_bytes);
@@ -51,9 +68,11 @@ mod test {
     }
 
     #[test]
-    #[ignore]
     fn test_validate() {
-        unimplemented!("Currently not sure how to do this as validate takes a filename as input. Will probably need to refactor")
+        let valid = include_str!("../examples/.dit");
+        let report = validate_read::<ActionA, _>(Cursor::new(valid)).expect("Should be readable");
+        assert!(report.is_valid());
+        assert_eq!(report.links().len(), 2);
     }
 
     #[test]
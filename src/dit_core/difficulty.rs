@@ -0,0 +1,128 @@
+//! Helpers for [`Action::bit_cost`] when an action's difficulty depends on
+//! the history of the book, not just its state.
+//!
+//! Since both mining and validation get the bit cost from
+//! [`Action::bit_cost`] with the same ledger, costs built from these are
+//! checked the same way they were mined.
+//!
+//! Changing the costs of an existing mode would make its old books invalid,
+//! so rules like these belong in a new mode, or behind a version in the
+//! state.
+//!
+//! ```ignore
+//! fn bit_cost(&self, ledger: &Ledger<Self>, _state: &Self::State) -> usize {
+//!     // Each repeat of the same action in a row costs 2 more bits, up to 16
+//!     let repeats = streak(ledger, |previous| previous == self);
+//!     capped(8 + 2 * repeats, 16)
+//! }
+//! ```
+
+use super::{Action, Ledger};
+
+/// How many messages in a row, counting back from the end of `ledger`, have
+/// actions that `matches` is true for.
+pub fn streak<A, F>(ledger: &Ledger<A>, matches: F) -> usize
+where
+    A: Action,
+    F: Fn(&A) -> bool,
+{
+    ledger
+        .messages()
+        .iter()
+        .rev()
+        .take_while(|message| matches(message.action()))
+        .count()
+}
+
+/// How many messages in `ledger` have actions that `matches` is true for.
+pub fn count<A, F>(ledger: &Ledger<A>, matches: F) -> usize
+where
+    A: Action,
+    F: Fn(&A) -> bool,
+{
+    ledger
+        .messages()
+        .iter()
+        .filter(|message| matches(message.action()))
+        .count()
+}
+
+/// A cost of `base` bits that goes up by one bit for every `messages_per_bit`
+/// messages already in the book, so books get harder as they get longer.
+///
+/// A `messages_per_bit` of 0 never ramps up.
+pub fn length_ramp<A: Action>(ledger: &Ledger<A>, base: usize, messages_per_bit: usize) -> usize {
+    base + ledger
+        .messages()
+        .len()
+        .checked_div(messages_per_bit)
+        .unwrap_or(0)
+}
+
+/// Keeps a cost from going over `max` bits, so that it stays possible to mine
+/// however the history goes.
+pub fn capped(bit_cost: usize, max: usize) -> usize {
+    bit_cost.min(max)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::mode_a::ActionA;
+    use super::super::{Ledger, Message};
+    use super::{capped, count, length_ramp, streak};
+
+    fn ledger_of(actions: Vec<ActionA>) -> Vec<Message<ActionA>> {
+        let state = Default::default();
+        let mut messages: Vec<Message<ActionA>> = Vec::new();
+        for action in actions {
+            let previous = messages.last().cloned().unwrap_or_default();
+            let ledger = Ledger::from(&messages[..]);
+            let message = previous.gen_next_message(action, &ledger, &state);
+            messages.push(message);
+        }
+        messages
+    }
+
+    fn is_noop(action: &ActionA) -> bool {
+        matches!(action, ActionA::NoOp)
+    }
+
+    #[test]
+    fn streak_counts_back_from_end() {
+        let marker = ActionA::Marker {
+            content: String::from("hi"),
+        };
+        let messages = ledger_of(vec![ActionA::NoOp, marker, ActionA::NoOp, ActionA::NoOp]);
+        let ledger = Ledger::from(&messages[..]);
+        assert_eq!(streak(&ledger, is_noop), 2);
+        assert_eq!(count(&ledger, is_noop), 3);
+        assert_eq!(streak(&Ledger::new(), is_noop), 0);
+    }
+
+    #[test]
+    fn repeats_can_cost_more() {
+        let marker = ActionA::Marker {
+            content: String::from("hi"),
+        };
+        let repeat_cost = |messages: &[Message<ActionA>]| {
+            capped(8 + 2 * streak(&Ledger::from(messages), is_noop), 12)
+        };
+        let messages = ledger_of(vec![ActionA::NoOp, marker, ActionA::NoOp, ActionA::NoOp]);
+        let costs: Vec<usize> = (0..=messages.len())
+            .map(|len| repeat_cost(&messages[..len]))
+            .collect();
+        assert_eq!(costs, [8, 10, 8, 10, 12]);
+    }
+
+    #[test]
+    fn length_ramp_goes_up_every_n_messages() {
+        let messages = ledger_of(vec![ActionA::NoOp; 5]);
+        assert_eq!(length_ramp(&Ledger::from(&messages[..2]), 3, 2), 4);
+        assert_eq!(length_ramp(&Ledger::from(&messages[..]), 3, 2), 5);
+        assert_eq!(length_ramp(&Ledger::from(&messages[..]), 3, 0), 3);
+        assert_eq!(
+            capped(length_ramp(&Ledger::from(&messages[..]), 3, 1), 6),
+            6
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! The hash functions that can be used to link messages together.
+
+use blake2::Blake2b;
+use digest::DynDigest;
+use sha3::{Digest, Sha3_224, Sha3_256};
+use std::fmt;
+
+/// A hash function used to link each message to the one before it.
+///
+/// A book records which one it uses in its header, so that new versions of
+/// the spec can switch hash functions without breaking older books.
+pub trait LinkHasher: Send + Sync {
+    /// The name the hash function is recorded as in a book's header.
+    fn name(&self) -> &'static str;
+
+    /// Starts a new, empty digest.
+    fn new_digest(&self) -> Box<dyn DynDigest + Send + Sync>;
+}
+
+/// The hash functions supported by `dit_core`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// The original hash function, used by any book that doesn't say otherwise.
+    #[default]
+    Sha3_224,
+    Sha3_256,
+    Blake2b,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 3] = [
+        HashAlgorithm::Sha3_224,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Blake2b,
+    ];
+
+    /// Looks up a hash algorithm by the name it is recorded as in headers.
+    pub fn from_name(name: &str) -> Option<HashAlgorithm> {
+        HashAlgorithm::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name() == name)
+    }
+}
+
+impl LinkHasher for HashAlgorithm {
+    fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha3_224 => "sha3-224",
+            HashAlgorithm::Sha3_256 => "sha3-256",
+            HashAlgorithm::Blake2b => "blake2b",
+        }
+    }
+
+    fn new_digest(&self) -> Box<dyn DynDigest + Send + Sync> {
+        match self {
+            HashAlgorithm::Sha3_224 => Box::new(Sha3_224::new()),
+            HashAlgorithm::Sha3_256 => Box::new(Sha3_256::new()),
+            HashAlgorithm::Blake2b => Box::new(Blake2b::new()),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HashAlgorithm, LinkHasher};
+
+    #[test]
+    fn hash_algorithms_are_found_by_name() {
+        for algorithm in HashAlgorithm::ALL.iter() {
+            assert_eq!(HashAlgorithm::from_name(algorithm.name()), Some(*algorithm));
+        }
+        assert_eq!(HashAlgorithm::from_name("md5"), None);
+    }
+
+    #[test]
+    fn hash_algorithms_give_different_hashes() {
+        let hashes: Vec<Box<[u8]>> = HashAlgorithm::ALL
+            .iter()
+            .map(|algorithm| {
+                let mut digest = algorithm.new_digest();
+                digest.update(b"dit");
+                digest.finalize()
+            })
+            .collect();
+        assert_eq!(hashes[0].len(), 28);
+        assert_eq!(hashes[1].len(), 32);
+        assert_eq!(hashes[2].len(), 64);
+    }
+}
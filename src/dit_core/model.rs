@@ -1,30 +1,70 @@
 mod action_interface;
 mod book;
+mod diagnostic;
+mod diff;
 mod error;
+mod estimate;
+mod header;
 mod hex_string;
+mod identity;
 mod ledger;
 mod message;
+mod miner;
+mod report;
 
 pub use action_interface::ActionInterface;
 pub use book::Book;
+pub use diagnostic::Diagnostic;
+pub use diff::BookDiff;
 pub use error::Error;
+pub use estimate::{expected_attempts, Estimate, MiningStats};
+pub use header::Header;
 pub use hex_string::HexString;
-pub use ledger::{Ledger, PendingLedger};
+pub(crate) use identity::check_signature;
+pub use identity::{Identity, SignatureCheck};
+pub use ledger::{Ledger, LedgerBuf, LedgerRng, PendingLedger, RandomSource};
 pub use message::Message;
+pub use miner::{CancellationToken, Miner, NonceSource, StopReason};
+pub use report::{
+    FailureSpan, ForensicReport, LinkFailure, LinkReport, RebaseReport, ValidationReport,
+};
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Copy, Clone, Debug, Deserialize, Serialize)]
-pub enum Mode {
-    // Should this be an enum, or just a newtape for Char? Opens possibility for many unicode characters instead of just whatever is defined here, allowing for unofficial modes
-    A,
-    B,
-    N,
+/// Identifies which set of rules a book is played by.
+///
+/// A mode is a single character, written as the first line of a book. Any
+/// character can be used, so unofficial modes can pick their own without
+/// changing `dit_core`. See [`ModeRegistry`](super::ModeRegistry) for finding
+/// the [`Action`](super::Action) that implements a mode.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct Mode(char);
+
+impl Mode {
+    pub const A: Mode = Mode('A');
+    pub const B: Mode = Mode('B');
+    pub const N: Mode = Mode('N');
+
+    pub const fn new(id: char) -> Self {
+        Mode(id)
+    }
+
+    pub fn id(&self) -> char {
+        self.0
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::HexString;
+    use super::{HexString, Mode};
     use rand::{thread_rng, Rng};
 
     #[test]
@@ -33,7 +73,7 @@ mod test {
             let random_n = thread_rng().gen::<u32>();
             let hex_string = HexString::from(&random_n.to_le_bytes()[..]);
             let mut hex_bytes: [u8; 4] = [0; 4];
-            for (i, byte) in (0..).zip(hex_string.to_bytes().into_iter()) {
+            for (i, byte) in (0..).zip(hex_string.to_bytes()) {
                 hex_bytes[i] = byte;
             }
             let converted = u32::from_le_bytes(hex_bytes);
@@ -59,4 +99,12 @@ mod test {
     fn tampered_message_is_not_accepted_by_previous_message() {
         todo!("Create a message, serialize it to string, replace the content with string replace (Use a non hex character), then deserialize it and see that it doesn't validate. Test with assert!(!..), not should_panic");
     }
+
+    #[test]
+    fn modes_are_written_as_strings() {
+        assert_eq!(serde_json::to_string(&Mode::A).unwrap(), "\"A\"");
+        let unofficial: Mode = serde_json::from_str("\"λ\"").unwrap();
+        assert_eq!(unofficial, Mode::new('λ'));
+        assert!(serde_json::from_str::<Mode>("\"AB\"").is_err());
+    }
 }
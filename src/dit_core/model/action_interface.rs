@@ -1,13 +1,18 @@
-use super::super::Action;
-use super::{Error, HexString, Ledger, Message};
+use super::super::{Action, HashAlgorithm, State};
+use super::{
+    Book, CancellationToken, Error, HexString, Identity, Ledger, Message, Miner, MiningStats,
+    NonceSource,
+};
+use std::time::Duration;
 
 /// WIP not sure quite how to do this.
 /// A struct that can be passed with the execution of an action to have certain things happen
 pub struct ActionInterface {
-    invalid: Box<dyn Fn() -> ()>, // Should accept Error here, instead of returning result from `run`
-    iter: Box<dyn Fn(HexString) -> ()>,
-    success: Box<dyn Fn(&HexString) -> ()>,
-    iter_period: u32,
+    invalid: Box<dyn Fn()>, // Should accept Error here, instead of returning result from `run`
+    success: Box<dyn Fn(&HexString)>,
+    miner: Miner<'static>,
+    identity: Option<Identity>,
+    hash_algorithm: HashAlgorithm,
 }
 
 impl ActionInterface {
@@ -17,7 +22,7 @@ impl ActionInterface {
 
     pub fn on_fail<F>(&mut self, fail_function: F) -> &mut Self
     where
-        F: Fn() -> () + 'static,
+        F: Fn() + 'static,
     {
         self.invalid = Box::new(fail_function);
         self
@@ -25,26 +30,92 @@ impl ActionInterface {
 
     pub fn on_success<F>(&mut self, success_function: F) -> &mut Self
     where
-        F: Fn(&HexString) -> () + 'static,
+        F: Fn(&HexString) + 'static,
     {
         self.success = Box::new(success_function);
         self
     }
 
     // On attempt would be a better name
+    /// The hook may be called from any of the mining threads.
     pub fn on_iter<F>(&mut self, iter_hook: F) -> &mut Self
     where
-        F: Fn(HexString) -> () + 'static,
+        F: Fn(HexString) + Send + Sync + 'static,
     {
-        self.iter = Box::new(iter_hook);
+        self.miner.on_iter(iter_hook);
+        self
+    }
+
+    /// Live statistics while mining, like the hash rate and how long is left.
+    /// See [`Miner::on_progress`].
+    pub fn on_progress<F>(&mut self, progress_hook: F) -> &mut Self
+    where
+        F: Fn(&MiningStats) + Send + Sync + 'static,
+    {
+        self.miner.on_progress(progress_hook);
         self
     }
 
     pub fn with_period(&mut self, period: u32) -> &mut Self {
-        self.iter_period = period;
+        self.miner.with_period(period);
+        self
+    }
+
+    /// How many threads to mine with, see [`Miner::with_threads`].
+    pub fn with_threads(&mut self, threads: usize) -> &mut Self {
+        self.miner.with_threads(threads);
+        self
+    }
+
+    /// Stops mining once `token` is cancelled, see [`Miner::with_cancellation`].
+    pub fn with_cancellation(&mut self, token: CancellationToken) -> &mut Self {
+        self.miner.with_cancellation(token);
+        self
+    }
+
+    /// Gives up on mining after `timeout`, see [`Miner::with_timeout`].
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.miner.with_timeout(timeout);
+        self
+    }
+
+    /// Gives up on mining after `max_attempts`, see [`Miner::with_max_attempts`].
+    pub fn with_max_attempts(&mut self, max_attempts: u64) -> &mut Self {
+        self.miner.with_max_attempts(max_attempts);
+        self
+    }
+
+    /// Sets where the keys to try come from, see [`Miner::with_nonce_source`].
+    pub fn with_nonce_source(&mut self, nonces: NonceSource) -> &mut Self {
+        self.miner.with_nonce_source(nonces);
         self
     }
 
+    /// Signs messages appended with [`run_on`](ActionInterface::run_on) as the
+    /// player with `identity`.
+    pub fn with_identity(&mut self, identity: Identity) -> &mut Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Sets the hash algorithm [`run`](ActionInterface::run) mines with,
+    /// which should be the one from the book's header. Books used with
+    /// [`run_on`](ActionInterface::run_on) already know theirs.
+    pub fn with_hash_algorithm(&mut self, hash_algorithm: HashAlgorithm) -> &mut Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// The identity messages are signed with, if any.
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    /// The miner set up by this interface.
+    pub fn miner(&self) -> &Miner<'static> {
+        &self.miner
+    }
+
     pub fn run<A: Action>(
         &self,
         action: A,
@@ -54,23 +125,28 @@ impl ActionInterface {
         // TODO init()
         // TODO return results from hooks
 
-        if action.applicable(&ledger, &state) {
-            let next_message = ledger
-                .messages()
-                .last()
-                .unwrap_or(&Message::<A>::default())
-                .gen_next_message_with_hook(action, &state, &self.iter, self.iter_period);
+        if let Err(err) = action.applicable(&ledger, &state) {
+            (*self.invalid)();
+            Err(err.at_message(ledger.messages().len()))
+        } else {
+            let genesis = Message::genesis(state.root_hash());
+            let next_message = self.miner.mine_with(
+                &self.hash_algorithm,
+                ledger.messages().last().unwrap_or(&genesis),
+                action,
+                &ledger,
+                &state,
+            )?;
 
             let result = next_message
                 .action()
                 .apply(&ledger.with_hash(next_message.key()), state)
-                .map(|_| (*self.success)(next_message.key()));
+                .map(|_| (*self.success)(next_message.key()))
+                .map_err(|err| err.at_message(ledger.messages().len()));
             if result.is_err() {
                 (*self.invalid)();
             }
             result
-        } else {
-            Ok((*self.invalid)())
         }
 
         // Should probably take a state as an input
@@ -83,15 +159,43 @@ impl ActionInterface {
         // Then call success with State
         // Ok(())
     }
+
+    /// Mines a message for `action` and appends it to `book`.
+    ///
+    /// The fail hook is called if the action can't be applied, and the
+    /// success hook with the new key once it has been. Changes still need to
+    /// be saved with [`Book::write_changes`]. If the interface has an
+    /// identity, the message is signed with it.
+    pub fn run_on<A: Action>(&self, action: A, book: &mut Book<A>) -> Result<(), Error<A>> {
+        if let Err(err) = book.applicable(&action) {
+            (*self.invalid)();
+            return Err(err);
+        }
+        let appended = match &self.identity {
+            Some(identity) => book.append_signed(action, &self.miner, identity),
+            None => book.append(action, &self.miner),
+        };
+        match appended {
+            Ok(message) => {
+                (*self.success)(message.key());
+                Ok(())
+            }
+            Err(err) => {
+                (*self.invalid)();
+                Err(err)
+            }
+        }
+    }
 }
 
 impl Default for ActionInterface {
     fn default() -> Self {
         ActionInterface {
-            iter_period: u32::MAX,
             invalid: Box::new(|| {}),
-            iter: Box::new(|_| {}),
             success: Box::new(|_| {}),
+            miner: Miner::new(),
+            identity: None,
+            hash_algorithm: HashAlgorithm::default(),
         }
     }
 }
@@ -1,110 +1,447 @@
 //! Ideally, the ultimate representation of the dit model.
 
+use super::super::ops::read_lines;
 use super::super::{Action, State};
-use super::{Ledger, Message};
-use std::io::{self, BufRead as _, BufReader, Read, Write};
-use std::iter::Iterator;
+use super::{
+    check_signature, BookDiff, Error, Header, HexString, Identity, Ledger, Message, Miner,
+    RebaseReport, SignatureCheck,
+};
+use std::io::{self, BufReader, Read, Write};
 
 type MessageVec<A> = Vec<Message<A>>;
 
 #[derive(Clone)]
 pub struct Book<A: Action> {
+    header: Header,
+    header_saved: bool,
     saved_lines: usize,
+    needs_rewrite: bool,
     messages: MessageVec<A>,
+    bit_costs: Vec<usize>,
     state: A::State,
+    root_hash: HexString,
+    public_key: Option<HexString>,
 }
 
 impl<A: Action> Book<A> {
+    /// Creates an empty book with a header for the mode of `A` and a new
+    /// random root hash.
+    pub fn new() -> Self {
+        Self::empty(Header::new(A::State::mode()).with_new_root_hash())
+    }
+
+    /// Creates an empty book belonging to the player with `identity`, whose
+    /// messages can be signed with [`append_signed`](Book::append_signed).
+    pub fn new_signed(identity: &Identity) -> Self {
+        Self::empty(
+            Header::new(A::State::mode())
+                .with_new_root_hash()
+                .with_public_key(&identity.public_key()),
+        )
+    }
+
+    /// Creates an empty book with the given header. The header is written out
+    /// with the first call to [`write_changes`](Book::write_changes).
+    pub fn with_header(header: Header) -> Result<Self, Error<A>> {
+        header.check_mode()?;
+        Ok(Self::empty(header))
+    }
+
+    fn empty(header: Header) -> Self {
+        let state: A::State = header.initial_state();
+        Book {
+            root_hash: state.root_hash(),
+            public_key: header.public_key(),
+            state,
+            header,
+            header_saved: false,
+            saved_lines: 0,
+            needs_rewrite: false,
+            messages: Vec::new(),
+            bit_costs: Vec::new(),
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
     pub fn ledger(&self) -> Ledger<'_, A> {
-        Ledger::from(&self.messages[..])
+        Ledger::from(&self.messages[..]).with_root_hash(&self.root_hash)
+    }
+
+    pub fn messages(&self) -> &[Message<A>] {
+        &self.messages[..]
     }
 
     pub fn state(&self) -> &A::State {
         &self.state
     }
 
-    pub fn write_changes<W: Write>(&mut self, writer: &mut W) {
-        self.write_pending_changes(writer);
+    /// How many bits had to match to mine each message, in order.
+    pub fn bit_costs(&self) -> &[usize] {
+        &self.bit_costs[..]
+    }
+
+    /// Whether messages that were already saved have been removed, so the
+    /// file has to be rewritten with [`write_all`](Book::write_all) instead of
+    /// appended to with [`write_changes`](Book::write_changes).
+    pub fn needs_rewrite(&self) -> bool {
+        self.needs_rewrite
+    }
+
+    /// Writes out the whole book, header and all.
+    pub fn write_all<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.header.write_to(writer)?;
+        self.messages
+            .iter()
+            .try_for_each(|line| writeln!(writer, "{}", line))?;
+        self.header_saved = true;
+        self.saved_lines = self.messages.len();
+        self.needs_rewrite = false;
+        Ok(())
+    }
+
+    /// Writes out anything added since the book was last saved.
+    pub fn write_changes<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.write_pending_changes(writer)?;
+        self.header_saved = true;
         self.saved_lines = self.messages.len();
+        Ok(())
     }
 
     pub fn write_pending_changes<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        if !self.header_saved {
+            self.header.write_to(writer)?;
+        }
         self.messages
             .iter()
             .skip(self.saved_lines)
             .try_for_each(|line| writeln!(writer, "{}", line))
     }
 
-    // Temporarily ignored until we have a way to write headers
-    fn from_read_header<I>(i: I) -> A::State
-    where
-        I: Iterator<Item = Result<String, io::Error>>,
-    {
-        i.take_while(|line| (&line).is_ok() && line.as_ref().unwrap() == "\"\"")
-            .fold(A::State::default(), |state, header_line| {
-                state.read_header_line(header_line.unwrap().as_str())
-            })
+    /// Reads a book, checking that every message is properly linked to the
+    /// one before it.
+    ///
+    /// A broken link is reported as [`Error::BrokenLink`].
+    pub fn from_read<R: Read>(r: R) -> Result<Book<A>, Error<A>> {
+        Self::read_checked(r, true)
     }
 
-    pub fn from_read<R: Read>(r: R) -> Result<Book<A>, super::Error<A>> {
-        let reader = BufReader::new(r);
-        let iter = reader.lines();
-        // let mode: String = iter.next();
-        // do some mode checking
-        let mut state = A::State::default(); //Self::from_read_header(iter.by_ref());
-
-        let messages: MessageVec<A> = iter
-            .map(|line_result| {
-                line_result
-                    .map_err(super::Error::IoError2)
-                    .and_then(|line| {
-                        serde_json::from_str::<Message<A>>(line.as_str())
-                            .map_err(super::Error::SerdeError)
-                    })
-            })
-            .collect::<Result<_, _>>()?;
-        let saved_lines = messages.len();
-        let state = (0..saved_lines).try_fold(A::State::default(), |state, n| {
-            let (messages_to_point, rest) = messages.split_at(n);
-            let next_message = rest.first().unwrap();
-            Self::apply_message_internal(messages_to_point, next_message, state)
-        })?;
+    /// Reads a book without checking the links between messages.
+    ///
+    /// Only use this for files that have already been validated, such as ones
+    /// this program wrote itself. Actions are still checked to be applicable.
+    pub fn from_read_trusted<R: Read>(r: R) -> Result<Book<A>, Error<A>> {
+        Self::read_checked(r, false)
+    }
 
-        Ok(Book {
-            messages,
-            saved_lines,
-            state,
-        })
+    fn read_checked<R: Read>(r: R, check_links: bool) -> Result<Book<A>, Error<A>> {
+        let (header, message_lines) = read_lines(BufReader::new(r))?;
+        let mut book = Book::with_header(header)?;
+        book.header_saved = true;
+        for message_result in message_lines {
+            let (line_number, message) = message_result?;
+            if check_links {
+                book.check_link(&message, Some(line_number))?;
+            }
+            book.push_message(message)
+                .map_err(|err| err.on_line(line_number))?;
+        }
+        book.saved_lines = book.messages.len();
+        Ok(book)
     }
 
-    fn apply_message_internal(
-        message_slice: &[Message<A>],
-        msg: &Message<A>,
-        state: A::State,
-    ) -> Result<A::State, super::Error<A>> {
+    /// Checks that `msg` is properly linked to the last message in the book,
+    /// and that it was signed by the player whose book this is if it is signed.
+    fn check_link(&self, msg: &Message<A>, line_number: Option<usize>) -> Result<(), Error<A>> {
+        let hasher = self.header.hash_algorithm()?;
+        let last_message = self.last_message();
+        if !last_message.accepts_next_message_with(&hasher, msg, &self.ledger(), &self.state) {
+            return Err(Error::BrokenLink {
+                line_number,
+                last_message: Box::new(last_message),
+                failed_message: Box::new(msg.clone()),
+            });
+        }
+        match check_signature(self.public_key.as_ref(), msg) {
+            SignatureCheck::Invalid => Err(Error::BadSignature {
+                line_number,
+                message: Box::new(msg.clone()),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn push_message(&mut self, msg: Message<A>) -> Result<(), Error<A>> {
         let action = msg.action();
-        let ledger = Ledger::from(message_slice);
-        if action.applicable(&ledger, &state) {
-            action.apply(&ledger.with_hash(msg.key()), state)
-        } else {
-            Err(super::Error::BadAction)
-        }
-    }
-
-    pub fn apply_message(&mut self, msg: Message<A>) -> Result<&mut Self, super::Error<A>> {
-        // Is this really the right pattern of ownership? It seems like this will create a lot of copies of default state...
-        // Perhaps clone() would be better, since we need the Book to survive the message being applied. This function
-        // is only invoked on new action, not on loading messages, so optimization is not as heavily needed (especially)
-        // considering that we have a specifically time-consuming proof-of-work generator function
-        // Or perhaps just don't pass it as mutable?
-        self.state = Self::apply_message_internal(
-            &self.messages[..],
-            &msg,
+        let index = self.messages.len();
+        let ledger = Ledger::from(&self.messages[..]).with_root_hash(&self.root_hash);
+        action
+            .applicable(&ledger, &self.state)
+            .map_err(|err| err.at_message(index))?;
+        let bit_cost = action.bit_cost(&ledger, &self.state);
+        let result = action.apply(
+            &ledger.with_hash(msg.key()),
             std::mem::take(&mut self.state),
-        )?;
+        );
+        match result {
+            Ok(state) => self.state = state,
+            Err(err) => {
+                // The failed apply used up the state, so rebuild it
+                self.state = self.replay_state()?;
+                return Err(err.at_message(index));
+            }
+        }
         self.messages.push(msg);
+        self.bit_costs.push(bit_cost);
+        Ok(())
+    }
+
+    /// Rebuilds the state from the header and every message in the book.
+    fn replay_state(&self) -> Result<A::State, Error<A>> {
+        self.messages.iter().zip(0..).try_fold(
+            self.header.initial_state(),
+            |state, (msg, index)| {
+                msg.action().apply(
+                    &Ledger::from(&self.messages[..index])
+                        .with_root_hash(&self.root_hash)
+                        .with_hash(msg.key()),
+                    state,
+                )
+            },
+        )
+    }
+
+    /// How many messages at the start of this book are the same as the start
+    /// of `other`, going by their keys.
+    pub fn common_prefix(&self, other: &Book<A>) -> usize {
+        self.messages
+            .iter()
+            .zip(other.messages.iter())
+            .take_while(|(msg, other_msg)| msg.key() == other_msg.key())
+            .count()
+    }
+
+    /// Merges `theirs` into this book, keeping this book's history and
+    /// re-mining the actions only `theirs` has on top of it.
+    ///
+    /// `ancestor` is the book both sides started from. Messages `theirs` shares
+    /// with either this book or `ancestor` aren't re-mined, so messages undone
+    /// on this side stay undone.
+    ///
+    /// If any of their actions can't be applied, this book is left as it was
+    /// and [`Error::MergeConflict`] is returned.
+    /// Re-mined messages are signed as in [`rebase`](Book::rebase).
+    pub fn merge(
+        &mut self,
+        ancestor: &Book<A>,
+        theirs: &Book<A>,
+        miner: &Miner,
+        identity: Option<&Identity>,
+    ) -> Result<RebaseReport<A>, Error<A>> {
+        let original_len = self.messages.len();
+        let shared = self
+            .common_prefix(theirs)
+            .max(ancestor.common_prefix(theirs));
+        let actions = theirs.messages[shared..]
+            .iter()
+            .map(|msg| msg.action().clone());
+        let result = self.rebase(actions, miner, identity).and_then(|report| {
+            if report.skipped().is_empty() {
+                Ok(report)
+            } else {
+                Err(Error::MergeConflict {
+                    skipped: report
+                        .into_skipped()
+                        .into_iter()
+                        .map(|(index, err)| (shared + index, err))
+                        .collect(),
+                })
+            }
+        });
+        if result.is_err() {
+            self.truncate_to(original_len)?;
+        }
+        result
+    }
+
+    /// Compares this book with `other`, see [`BookDiff`].
+    pub fn diff<'a>(&'a self, other: &'a Book<A>) -> BookDiff<'a, A> {
+        BookDiff::new(self, other)
+    }
+
+    /// Mines each of `actions` onto the end of this book, skipping any that
+    /// can no longer be applied.
+    ///
+    /// Actions are checked against the state as it is when their turn comes,
+    /// so an action can be skipped because of an action rebased before it.
+    /// If mining stops early, the error is returned and the actions mined so
+    /// far stay in the book.
+    ///
+    /// If the book belongs to a player, the messages are signed with
+    /// `identity`, which has to be theirs.
+    pub fn rebase<I>(
+        &mut self,
+        actions: I,
+        miner: &Miner,
+        identity: Option<&Identity>,
+    ) -> Result<RebaseReport<A>, Error<A>>
+    where
+        I: IntoIterator<Item = A>,
+    {
+        if self.public_key.is_some() && identity.is_none() {
+            return Err(Error::WrongIdentity {
+                public_key: self.public_key.clone(),
+            });
+        }
+        let mut report = RebaseReport::new();
+        for (action, index) in actions.into_iter().zip(0..) {
+            let appended = match identity {
+                Some(identity) if self.public_key.is_some() => {
+                    self.append_signed(action, miner, identity)
+                }
+                _ => self.append(action, miner),
+            };
+            match appended {
+                Ok(_) => report.rebase(index),
+                Err(err @ Error::BadAction { .. }) => report.skip(index, err),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Starts a new book from messages `0..=n` of this one, leaving this one
+    /// as it is.
+    ///
+    /// The new book's header records the key of message `n`, so tools can
+    /// tell where the two books share history. See [`Header::parent`].
+    pub fn fork(&self, n: usize) -> Result<Book<A>, Error<A>> {
+        let fork_point = self.messages.get(n).ok_or(Error::NoSuchMessage {
+            index: n,
+            message_count: self.messages.len(),
+        })?;
+        let header = self.header.clone().with_parent(n + 1, fork_point.key());
+        let mut book = Book::with_header(header)?;
+        for msg in self.messages[..=n].iter() {
+            book.push_message(msg.clone())?;
+        }
+        Ok(book)
+    }
+
+    /// Removes every message after the first `n`, and rebuilds the state from
+    /// the messages that are left. Returns the removed messages.
+    ///
+    /// If any removed messages were already saved, the file will need to be
+    /// rewritten, see [`needs_rewrite`](Book::needs_rewrite).
+    pub fn truncate_to(&mut self, n: usize) -> Result<MessageVec<A>, Error<A>> {
+        if n >= self.messages.len() {
+            return Ok(Vec::new());
+        }
+        let mut book = Book::with_header(self.header.clone())?;
+        for msg in self.messages[..n].iter() {
+            book.push_message(msg.clone())?;
+        }
+        book.header_saved = self.header_saved;
+        book.saved_lines = self.saved_lines.min(n);
+        book.needs_rewrite = self.needs_rewrite || self.saved_lines > n;
+        let discarded = self.messages.split_off(n);
+        *self = book;
+        Ok(discarded)
+    }
+
+    /// How many messages from the start of the book are properly linked and
+    /// signed. This is all of them, unless the book was read with
+    /// [`from_read_trusted`](Book::from_read_trusted).
+    pub fn linked_len(&self) -> Result<usize, Error<A>> {
+        let mut book = Book::with_header(self.header.clone())?;
+        Ok(self
+            .messages
+            .iter()
+            .take_while(|msg| book.apply_message((*msg).clone()).is_ok())
+            .count())
+    }
+
+    /// Applies a message to the end of the book, after checking that it is
+    /// properly linked to the last message.
+    pub fn apply_message(&mut self, msg: Message<A>) -> Result<&mut Self, Error<A>> {
+        self.check_link(&msg, None)?;
+        self.apply_message_trusted(msg)
+    }
+
+    /// Applies a message to the end of the book without checking its link,
+    /// for messages that are known to be properly mined.
+    pub fn apply_message_trusted(&mut self, msg: Message<A>) -> Result<&mut Self, Error<A>> {
+        self.push_message(msg)?;
         Ok(self)
     }
+
+    /// The last message in the book, which the next message has to link to.
+    /// For an empty book, this is the [genesis](Message::genesis) message.
+    pub fn last_message(&self) -> Message<A> {
+        self.messages
+            .last()
+            .cloned()
+            .unwrap_or_else(|| Message::genesis(self.root_hash.clone()))
+    }
+
+    /// How many bits have to match to append `action` to the book as it is now.
+    pub fn bit_cost(&self, action: &A) -> usize {
+        action.bit_cost(&self.ledger(), &self.state)
+    }
+
+    /// Checks whether `action` can be applied to the book as it is now.
+    pub fn applicable(&self, action: &A) -> Result<(), Error<A>> {
+        action
+            .applicable(&self.ledger(), &self.state)
+            .map_err(|err| err.at_message(self.messages.len()))
+    }
+
+    /// Mines a message for `action` and applies it to the book.
+    ///
+    /// The new message is not saved until [`write_changes`](Book::write_changes)
+    /// is called. If the action isn't applicable, or mining stops early, the
+    /// book is left as it was.
+    pub fn append(&mut self, action: A, miner: &Miner) -> Result<&Message<A>, Error<A>> {
+        let message = self.mine(action, miner)?;
+        self.apply_message_trusted(message)?;
+        Ok(self.messages.last().unwrap())
+    }
+
+    /// Mines a message for `action` like [`append`](Book::append), and signs
+    /// it with `identity`.
+    ///
+    /// Fails with [`Error::WrongIdentity`] if this book doesn't belong to the
+    /// player with `identity`.
+    pub fn append_signed(
+        &mut self,
+        action: A,
+        miner: &Miner,
+        identity: &Identity,
+    ) -> Result<&Message<A>, Error<A>> {
+        if self.public_key != Some(identity.public_key()) {
+            return Err(Error::WrongIdentity {
+                public_key: self.public_key.clone(),
+            });
+        }
+        let message = self.mine(action, miner)?;
+        let signature = identity.sign(&message);
+        self.apply_message_trusted(message.with_signature(signature))?;
+        Ok(self.messages.last().unwrap())
+    }
+
+    fn mine(&self, action: A, miner: &Miner) -> Result<Message<A>, Error<A>> {
+        self.applicable(&action)?;
+        let hasher = self.header.hash_algorithm()?;
+        miner.mine_with(
+            &hasher,
+            &self.last_message(),
+            action,
+            &self.ledger(),
+            &self.state,
+        )
+    }
 }
 
 /*
@@ -118,20 +455,24 @@ impl <A: Action, R:Read> TryFrom<R> for Book<A> {
 
 impl<A: Action> Default for Book<A> {
     fn default() -> Book<A> {
-        Book {
-            saved_lines: 0,
-            messages: Vec::new(),
-            state: A::State::default(),
-        }
+        Book::new()
     }
 }
 
 #[cfg(test)]
 mod test {
 
-    use super::super::super::super::mode_a::ActionA;
+    use super::super::super::super::mode_a::{spells::Spell, ActionA};
+    use super::super::{Error, Header, HexString, Identity, Ledger, Message, Miner, Mode};
     use super::Book;
-    use std::io::Cursor; // Change later to some test action
+    use std::io::{Cursor, Write}; // Change later to some test action
+    use std::iter;
+
+    fn marker(content: &str) -> ActionA {
+        ActionA::Marker {
+            content: String::from(content),
+        }
+    }
 
     #[test]
     #[should_panic]
@@ -139,6 +480,425 @@ mod test {
         let cursor =
             Cursor::new("Bag of beans, barely even human\nsavages, savages, wrotten to the core");
 
-        let book = Book::<ActionA>::from_read(cursor).expect("Should be readable");
+        Book::<ActionA>::from_read(cursor).expect("Should be readable");
+    }
+
+    #[test]
+    fn new_book_writes_header_before_messages() {
+        let mut book = Book::<ActionA>::with_header(Header::new(Mode::A).with_line("hi")).unwrap();
+        let message =
+            Message::default().gen_next_message(ActionA::NoOp, &book.ledger(), book.state());
+        book.apply_message(message).unwrap();
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+
+        let written = String::from_utf8(written).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[..3], ["\"A\"", "\"hi\"", "\"---\""]);
+        assert_eq!(lines.len(), 4);
+
+        let read_book = Book::<ActionA>::from_read(Cursor::new(written)).unwrap();
+        assert_eq!(read_book.header().lines(), ["hi"]);
+        assert_eq!(read_book.ledger().messages().len(), 1);
+    }
+
+    #[test]
+    fn append_applies_action_to_state() {
+        let mut book = Book::<ActionA>::new();
+        let message = book
+            .append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap()
+            .clone();
+        assert_eq!(book.state().version(), 200);
+        let genesis = Message::genesis(book.header().root_hash().unwrap());
+        assert!(genesis.accepts_next_message(&message, &Ledger::new(), &Default::default()));
+
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+        let read_book = Book::<ActionA>::from_read(Cursor::new(written)).unwrap();
+        assert_eq!(read_book.state().version(), 200);
+    }
+
+    #[test]
+    fn append_refuses_inapplicable_action() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        assert!(book
+            .applicable(&ActionA::UpdateVersion { version: 150 })
+            .is_err());
+        match book.append(ActionA::UpdateVersion { version: 150 }, &Miner::new()) {
+            Err(Error::BadAction { index, reason, .. }) => {
+                assert_eq!(index, Some(1));
+                assert_eq!(reason, "the book is already on version 200");
+            }
+            _ => panic!("Expected a bad action"),
+        }
+        assert_eq!(book.ledger().messages().len(), 1);
+    }
+
+    #[test]
+    fn write_changes_only_writes_new_messages() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut appended = Vec::new();
+        book.write_changes(&mut appended).unwrap();
+        assert_eq!(String::from_utf8(appended).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn book_from_read_rejects_tampered_file() {
+        let tampered = include_str!("../../../examples/.tampered");
+        let result = Book::<ActionA>::from_read(Cursor::new(tampered));
+        assert!(matches!(
+            result,
+            Err(Error::BrokenLink {
+                line_number: Some(_),
+                ..
+            })
+        ));
+        assert!(Book::<ActionA>::from_read_trusted(Cursor::new(tampered)).is_ok());
+    }
+
+    #[test]
+    fn book_from_read_accepts_valid_file() {
+        let valid = include_str!("../../../examples/.dit");
+        let book = Book::<ActionA>::from_read(Cursor::new(valid)).unwrap();
+        assert_eq!(book.ledger().messages().len(), 2);
+    }
+
+    #[test]
+    fn new_books_have_their_own_root_hash() {
+        let book = Book::<ActionA>::new();
+        let other = Book::<ActionA>::new();
+        assert_ne!(book.header().root_hash(), other.header().root_hash());
+        let key = HexString::from(&[1, 2, 3, 4][..]);
+        assert_ne!(
+            book.ledger().with_hash(&key).key_back(1),
+            other.ledger().with_hash(&key).key_back(1)
+        );
+        assert_eq!(
+            book.last_message().key(),
+            &book.header().root_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn messages_from_another_book_are_rejected() {
+        let mut book = Book::<ActionA>::new();
+        book.append(marker("only in this book"), &Miner::new())
+            .unwrap();
+        let copied = book.messages()[0].clone();
+        // A cheap message can link to another root by luck, so pick a book
+        // it doesn't link to
+        let mut other = iter::repeat_with(Book::<ActionA>::new)
+            .find(|other| {
+                !other
+                    .last_message()
+                    .accepts_next_message(&copied, &other.ledger(), other.state())
+            })
+            .unwrap();
+        assert!(matches!(
+            other.apply_message(copied.clone()),
+            Err(Error::BrokenLink { .. })
+        ));
+
+        let mut written = Vec::new();
+        other.write_all(&mut written).unwrap();
+        writeln!(written, "{}", copied).unwrap();
+        assert!(matches!(
+            Book::<ActionA>::from_read(Cursor::new(written)),
+            Err(Error::BrokenLink {
+                line_number: Some(4),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn signed_messages_are_read_back() {
+        let identity = Identity::generate();
+        let mut book = Book::<ActionA>::new_signed(&identity);
+        book.append_signed(marker("mine"), &Miner::new(), &identity)
+            .unwrap();
+        book.append(marker("unsigned"), &Miner::new()).unwrap();
+        let mut written = Vec::new();
+        book.write_all(&mut written).unwrap();
+
+        let read_book = Book::<ActionA>::from_read(Cursor::new(written)).unwrap();
+        assert_eq!(read_book.header().public_key(), Some(identity.public_key()));
+        assert!(read_book.messages()[0].signature().is_some());
+        assert_eq!(read_book.messages()[1].signature(), None);
+    }
+
+    #[test]
+    fn signatures_from_another_player_are_rejected() {
+        let identity = Identity::generate();
+        let other = Identity::generate();
+        let mut book = Book::<ActionA>::new_signed(&identity);
+        assert!(matches!(
+            book.append_signed(marker("not mine"), &Miner::new(), &other),
+            Err(Error::WrongIdentity { .. })
+        ));
+        assert!(matches!(
+            Book::<ActionA>::new().append_signed(marker("nobody's"), &Miner::new(), &identity),
+            Err(Error::WrongIdentity { public_key: None })
+        ));
+
+        let message =
+            book.last_message()
+                .gen_next_message(marker("forged"), &book.ledger(), book.state());
+        let forged = message.clone().with_signature(other.sign(&message));
+        assert!(matches!(
+            book.apply_message(forged),
+            Err(Error::BadSignature {
+                line_number: None,
+                ..
+            })
+        ));
+        assert!(book.messages().is_empty());
+    }
+
+    #[test]
+    fn bit_costs_are_the_same_when_read() {
+        let fire_ball = || ActionA::CastSpell {
+            spell: Spell::FireBall,
+        };
+        let mut book = Book::<ActionA>::new();
+        book.append(fire_ball(), &Miner::new()).unwrap();
+        book.append(fire_ball(), &Miner::new()).unwrap();
+        book.append(marker("rest"), &Miner::new()).unwrap();
+        book.append(fire_ball(), &Miner::new()).unwrap();
+        assert_eq!(book.bit_costs(), [8, 8, 5, 8]);
+
+        let mut written = Vec::new();
+        book.write_all(&mut written).unwrap();
+        let read_book = Book::<ActionA>::from_read(Cursor::new(written)).unwrap();
+        assert_eq!(read_book.bit_costs(), book.bit_costs());
+    }
+
+    #[test]
+    fn apply_message_rejects_unlinked_message() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        // A NoOp is cheap enough to link to the real key by luck, so keep
+        // mining until it doesn't
+        let last_message = book.last_message();
+        let unlinked = iter::repeat_with(|| {
+            Message::default().gen_next_message(ActionA::NoOp, &book.ledger(), book.state())
+        })
+        .find(|message| !last_message.accepts_next_message(message, &book.ledger(), book.state()))
+        .unwrap();
+        assert!(matches!(
+            book.apply_message(unlinked.clone()),
+            Err(Error::BrokenLink {
+                line_number: None,
+                ..
+            })
+        ));
+        assert_eq!(book.ledger().messages().len(), 1);
+        book.apply_message_trusted(unlinked).unwrap();
+        assert_eq!(book.ledger().messages().len(), 2);
+    }
+
+    #[test]
+    fn linked_len_stops_at_first_broken_link() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let last_message = book.last_message();
+        let unlinked = iter::repeat_with(|| {
+            Message::default().gen_next_message(ActionA::NoOp, &book.ledger(), book.state())
+        })
+        .find(|message| !last_message.accepts_next_message(message, &book.ledger(), book.state()))
+        .unwrap();
+        book.apply_message_trusted(unlinked).unwrap();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        assert_eq!(book.linked_len().unwrap(), 1);
+        book.truncate_to(1).unwrap();
+        assert_eq!(book.linked_len().unwrap(), 1);
+    }
+
+    #[test]
+    fn truncate_to_rebuilds_state() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        book.append(ActionA::UpdateVersion { version: 300 }, &Miner::new())
+            .unwrap();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+        assert_eq!(book.bit_costs(), [1, 1, 5]);
+
+        let discarded = book.truncate_to(1).unwrap();
+        assert_eq!(discarded.len(), 2);
+        assert_eq!(book.state().version(), 200);
+        assert_eq!(book.bit_costs(), [1]);
+        assert!(book.needs_rewrite());
+        assert!(book.truncate_to(5).unwrap().is_empty());
+
+        let mut rewritten = Vec::new();
+        book.write_all(&mut rewritten).unwrap();
+        assert!(!book.needs_rewrite());
+        let read_book = Book::<ActionA>::from_read(Cursor::new(rewritten)).unwrap();
+        assert_eq!(read_book.ledger().messages().len(), 1);
+        assert_eq!(read_book.state().version(), 200);
+    }
+
+    #[test]
+    fn truncate_unsaved_messages_does_not_need_rewrite() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        book.write_changes(&mut Vec::new()).unwrap();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        book.truncate_to(1).unwrap();
+        assert!(!book.needs_rewrite());
+    }
+
+    #[test]
+    fn fork_copies_messages_up_to_fork_point() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        book.append(ActionA::UpdateVersion { version: 300 }, &Miner::new())
+            .unwrap();
+
+        let mut fork = book.fork(0).unwrap();
+        assert_eq!(fork.ledger().messages().len(), 1);
+        assert_eq!(fork.state().version(), 200);
+        assert_eq!(
+            fork.header().parent(),
+            Some((1, book.ledger().messages()[0].key().clone()))
+        );
+        assert_eq!(book.ledger().messages().len(), 2);
+        assert!(matches!(book.fork(2), Err(Error::NoSuchMessage { .. })));
+
+        let mut written = Vec::new();
+        fork.write_changes(&mut written).unwrap();
+        let read_fork = Book::<ActionA>::from_read(Cursor::new(written)).unwrap();
+        assert_eq!(read_fork.header().parent(), fork.header().parent());
+        assert_eq!(read_fork.ledger().messages().len(), 1);
+    }
+
+    #[test]
+    fn rebase_mines_actions_onto_other_book() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut branch = book.fork(0).unwrap();
+        branch
+            .append(ActionA::UpdateVersion { version: 150 }, &Miner::new())
+            .unwrap();
+        branch.append(ActionA::NoOp, &Miner::new()).unwrap();
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        assert_eq!(book.common_prefix(&branch), 1);
+
+        let actions: Vec<ActionA> = branch.ledger().messages()[1..]
+            .iter()
+            .map(|msg| msg.action().clone())
+            .collect();
+        let report = book.rebase(actions, &Miner::new(), None).unwrap();
+        assert_eq!(report.rebased(), [1]);
+        assert_eq!(report.skipped().len(), 1);
+        assert_eq!(report.skipped()[0].0, 0);
+        assert_eq!(book.ledger().messages().len(), 3);
+        assert_eq!(book.state().version(), 200);
+
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+        assert!(Book::<ActionA>::from_read(Cursor::new(written)).is_ok());
+    }
+
+    #[test]
+    fn rebase_signs_messages_in_signed_book() {
+        let identity = Identity::generate();
+        let mut book = Book::<ActionA>::new_signed(&identity);
+        assert!(matches!(
+            book.rebase(vec![ActionA::NoOp], &Miner::new(), None),
+            Err(Error::WrongIdentity { .. })
+        ));
+        book.rebase(vec![ActionA::NoOp], &Miner::new(), Some(&identity))
+            .unwrap();
+        assert!(book.messages()[0].signature().is_some());
+
+        let mut written = Vec::new();
+        book.write_all(&mut written).unwrap();
+        assert!(Book::<ActionA>::from_read(Cursor::new(written)).is_ok());
+    }
+
+    #[test]
+    fn merge_remines_their_actions() {
+        let mut ancestor = Book::<ActionA>::new();
+        ancestor.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut ours = ancestor.clone();
+        ours.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        let mut theirs = ancestor.clone();
+        theirs.append(marker("theirs"), &Miner::new()).unwrap();
+
+        let report = ours.merge(&ancestor, &theirs, &Miner::new(), None).unwrap();
+        assert_eq!(report.rebased(), [0]);
+        assert_eq!(ours.messages().len(), 3);
+        assert_eq!(
+            ours.messages()[2].action().to_string(),
+            marker("theirs").to_string()
+        );
+        assert_eq!(ours.state().version(), 200);
+    }
+
+    #[test]
+    fn merge_conflict_leaves_book_alone() {
+        let ancestor = Book::<ActionA>::new();
+        let mut ours = ancestor.clone();
+        ours.append(ActionA::UpdateVersion { version: 300 }, &Miner::new())
+            .unwrap();
+        let mut theirs = ancestor.clone();
+        theirs.append(marker("fine"), &Miner::new()).unwrap();
+        theirs
+            .append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+
+        let result = ours.merge(&ancestor, &theirs, &Miner::new(), None);
+        match result {
+            Err(Error::MergeConflict { skipped }) => {
+                assert_eq!(skipped.len(), 1);
+                assert_eq!(skipped[0].0, 1);
+            }
+            _ => panic!("Expected a merge conflict"),
+        }
+        assert_eq!(ours.messages().len(), 1);
+        assert_eq!(ours.state().version(), 300);
+    }
+
+    #[test]
+    fn book_from_read_without_header() {
+        let legacy = include_str!("../../../examples/.dit");
+        let book = Book::<ActionA>::from_read(Cursor::new(legacy)).unwrap();
+        assert_eq!(book.header(), &Header::new(Mode::A));
+        assert_eq!(book.ledger().messages().len(), 2);
+    }
+
+    #[test]
+    fn book_from_read_wrong_mode() {
+        let cursor = Cursor::new("\"N\"\n\"---\"\n");
+        match Book::<ActionA>::from_read(cursor) {
+            Err(Error::WrongMode {
+                mode: Mode::N,
+                expected_modes,
+            }) => assert_eq!(expected_modes, vec![Mode::A]),
+            _ => panic!("Should be the wrong mode"),
+        }
+    }
+
+    #[test]
+    fn book_from_read_unterminated_header() {
+        let cursor = Cursor::new("\"A\"\n\"hi\"\n");
+        assert!(matches!(
+            Book::<ActionA>::from_read(cursor),
+            Err(Error::UnterminatedHeader)
+        ));
     }
 }
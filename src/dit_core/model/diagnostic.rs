@@ -0,0 +1,326 @@
+use super::super::Action;
+use super::{HexString, Message};
+use serde_json::Value;
+use std::fmt;
+use std::ops::Range;
+
+/// Points at the exact spot in a file where something went wrong, so it can
+/// be shown with the offending line quoted and the bad part underlined.
+///
+/// ```text
+/// unknown action type "markr"
+///  --> .dit:4:22
+///   |
+/// 4 | ["b5fb3141",{"type":"markr","content":"bagel bites"}]
+///   |                      ^^^^^
+///   = help: the types for this mode are `marker`, `updateversion`, ...
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    file_name: Option<String>,
+    line_number: usize,
+    line: String,
+    span: Range<usize>,
+    message: String,
+    help: Option<String>,
+}
+
+impl Diagnostic {
+    /// A diagnostic underlining the bytes in `span` of `line`, widened to
+    /// whole characters.
+    pub(crate) fn new<S: Into<String>>(
+        line_number: usize,
+        line: &str,
+        span: Range<usize>,
+        message: S,
+    ) -> Self {
+        let mut end = span.end.min(line.len());
+        while !line.is_char_boundary(end) {
+            end += 1;
+        }
+        let mut start = span.start.min(end);
+        while !line.is_char_boundary(start) {
+            start -= 1;
+        }
+        Diagnostic {
+            file_name: None,
+            line_number,
+            line: String::from(line),
+            span: start..end,
+            message: message.into(),
+            help: None,
+        }
+    }
+
+    /// A diagnostic underlining the whole of `line`.
+    pub(crate) fn whole_line<S: Into<String>>(line_number: usize, line: &str, message: S) -> Self {
+        Diagnostic::new(line_number, line, 0..line.len(), message)
+    }
+
+    pub(crate) fn with_help<S: Into<String>>(mut self, help: S) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub(crate) fn with_file_name(mut self, file_name: &str) -> Self {
+        self.file_name = Some(String::from(file_name));
+        self
+    }
+
+    /// Works out what is wrong with a message line that couldn't be read.
+    pub(crate) fn for_message_line<A: Action>(
+        line_number: usize,
+        line: &str,
+        err: serde_json::Error,
+    ) -> Self {
+        let help =
+            "each message should look like [\"<hex key>\",{\"type\":\"<action type>\", ...}], with an optional signature at the end";
+        let value: Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(_) => {
+                let column = err.column().saturating_sub(1);
+                return Diagnostic::new(line_number, line, column..column + 1, err.to_string())
+                    .with_help(help);
+            }
+        };
+        let (key, action, signature) = match value.as_array().map(Vec::as_slice) {
+            Some([key, action]) => (key, action, None),
+            Some([key, action, signature]) => (key, action, Some(signature)),
+            _ => {
+                return Diagnostic::whole_line(line_number, line, "this isn't a message")
+                    .with_help(help)
+            }
+        };
+        match key.as_str() {
+            Some(key) if HexString::is_valid(key) => (),
+            Some(key) => {
+                return Diagnostic::new(
+                    line_number,
+                    line,
+                    find_string(line, key, 0),
+                    format!("\"{}\" isn't a valid key", key),
+                )
+                .with_help("keys are an even number of the characters 0-9 and a-f")
+            }
+            None => {
+                return Diagnostic::whole_line(line_number, line, "the key should be a string")
+                    .with_help(help)
+            }
+        }
+        match signature.map(Value::as_str) {
+            Some(Some(signature)) if !HexString::is_valid(signature) => {
+                let from = line.rfind('}').unwrap_or(0);
+                return Diagnostic::new(
+                    line_number,
+                    line,
+                    find_string(line, signature, from),
+                    format!("\"{}\" isn't a valid signature", signature),
+                )
+                .with_help("signatures are an even number of the characters 0-9 and a-f");
+            }
+            Some(None) => {
+                return Diagnostic::whole_line(
+                    line_number,
+                    line,
+                    "the signature should be a string",
+                )
+                .with_help(help)
+            }
+            _ => (),
+        }
+        let action_start = line.find('{').unwrap_or(0);
+        let action_span = action_start..line.rfind('}').map_or(line.len(), |end| end + 1);
+        let action_err = match serde_json::from_value::<A>(action.clone()) {
+            Ok(_) => return Diagnostic::whole_line(line_number, line, err.to_string()),
+            Err(action_err) => action_err.to_string(),
+        };
+        match action.get("type").and_then(Value::as_str) {
+            Some(action_type) if action_err.starts_with("unknown variant") => Diagnostic::new(
+                line_number,
+                line,
+                find_type(line, action_type, action_start),
+                format!("unknown action type \"{}\"", action_type),
+            )
+            .with_help(match action_err.split_once("expected ") {
+                Some((_, expected)) => format!(
+                    "the types for this mode are {}",
+                    expected.trim_start_matches("one of ")
+                ),
+                None => action_err,
+            }),
+            Some(_) => Diagnostic::new(line_number, line, action_span, "this action is malformed")
+                .with_help(action_err),
+            None => Diagnostic::new(line_number, line, action_span, "this action has no type")
+                .with_help("actions need a \"type\", like {\"type\":\"marker\", ...}"),
+        }
+    }
+
+    /// Points at the key of a message that isn't linked to the one before.
+    pub(crate) fn for_broken_link<A: Action>(
+        line_number: usize,
+        last_message: &Message<A>,
+        failed_message: &Message<A>,
+    ) -> Self {
+        let line = failed_message.to_string();
+        let key = failed_message.key().to_string();
+        Diagnostic::new(
+            line_number,
+            &line,
+            find_string(&line, &key, 0),
+            format!(
+                "this message isn't linked to the message before it, {}",
+                last_message.key()
+            ),
+        )
+        .with_help(
+            "`dit validate --all` finds every broken link, then `dit undo` can go back to before the first",
+        )
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// Line of the file, starting at 1.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// Column of the start of the problem, starting at 1.
+    pub fn column(&self) -> usize {
+        self.line[..self.span.start].chars().count() + 1
+    }
+
+    /// The offending line, as it is in the file.
+    pub fn line(&self) -> &str {
+        self.line.as_str()
+    }
+
+    pub fn message(&self) -> &str {
+        self.message.as_str()
+    }
+
+    /// A suggestion for how to fix the problem.
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let gutter = " ".repeat(self.line_number.to_string().len());
+        let underline = self.line[self.span.clone()].chars().count().max(1);
+        writeln!(f, "{}", self.message)?;
+        match self.file_name() {
+            Some(file_name) => writeln!(
+                f,
+                "{}--> {}:{}:{}",
+                gutter,
+                file_name,
+                self.line_number,
+                self.column()
+            )?,
+            None => writeln!(
+                f,
+                "{}--> line {}, column {}",
+                gutter,
+                self.line_number,
+                self.column()
+            )?,
+        }
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line_number, self.line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column() - 1),
+            "^".repeat(underline)
+        )?;
+        if let Some(help) = &self.help {
+            write!(f, "\n{} = help: {}", gutter, help)?;
+        }
+        Ok(())
+    }
+}
+
+/// The span of the contents of the first JSON string `value` in `line` after
+/// `from`, or the rest of the line if it can't be found.
+fn find_string(line: &str, value: &str, from: usize) -> Range<usize> {
+    match line[from..].find(&format!("\"{}\"", value)) {
+        Some(start) => from + start + 1..from + start + 1 + value.len(),
+        None => from..line.len(),
+    }
+}
+
+/// The span of the value of the `"type"` field in `line`.
+fn find_type(line: &str, action_type: &str, from: usize) -> Range<usize> {
+    match line[from..].find("\"type\"") {
+        Some(start) => find_string(line, action_type, from + start + 6),
+        None => find_string(line, action_type, from),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::mode_a::ActionA;
+    use super::super::Message;
+    use super::Diagnostic;
+
+    fn diagnose(line: &str) -> Diagnostic {
+        let err = serde_json::from_str::<Message<ActionA>>(line).unwrap_err();
+        Diagnostic::for_message_line::<ActionA>(4, line, err).with_file_name(".dit")
+    }
+
+    #[test]
+    fn unknown_action_type_is_underlined() {
+        let diagnostic = diagnose(r#"["b5fb3141",{"type":"markr","content":"bagel bites"}]"#);
+        assert_eq!(diagnostic.message(), "unknown action type \"markr\"");
+        assert_eq!(diagnostic.column(), 22);
+        assert!(diagnostic.help().unwrap().contains("marker"));
+        let shown = diagnostic.to_string();
+        assert!(shown.contains(" --> .dit:4:22"));
+        assert!(shown.contains("\n  |                      ^^^^^\n"));
+    }
+
+    #[test]
+    fn non_hex_key_is_underlined() {
+        let diagnostic = diagnose(r#"["b5fb31zz",{"type":"marker","content":"bagel bites"}]"#);
+        assert_eq!(diagnostic.message(), "\"b5fb31zz\" isn't a valid key");
+        assert_eq!(diagnostic.column(), 3);
+        assert!(diagnostic.to_string().contains("  |   ^^^^^^^^\n"));
+    }
+
+    #[test]
+    fn broken_json_points_at_column() {
+        let diagnostic = diagnose(r#"["b5fb3141",{"type":"marker""#);
+        assert_eq!(diagnostic.line_number(), 4);
+        assert!(diagnostic.help().is_some());
+    }
+
+    #[test]
+    fn bad_signature_is_underlined() {
+        let diagnostic = diagnose(r#"["b5fb3141",{"type":"marker","content":"hi"},"5ig"]"#);
+        assert_eq!(diagnostic.message(), "\"5ig\" isn't a valid signature");
+        assert_eq!(diagnostic.column(), 47);
+    }
+
+    #[test]
+    fn multibyte_characters_are_underlined_whole() {
+        let diagnostic = diagnose(r#"["b5fb3141",{"type":"marker","content":"\λ"}]"#);
+        let shown = diagnostic.to_string();
+        assert!(shown.contains(&format!(" --> .dit:4:{}", diagnostic.column())));
+        let diagnostic = Diagnostic::new(1, "aλb", 2..3, "oops");
+        assert_eq!(diagnostic.column(), 2);
+        assert!(diagnostic.to_string().ends_with("1 | aλb\n  |  ^"));
+    }
+
+    #[test]
+    fn malformed_action_is_underlined() {
+        let line = r#"["b5fb3141",{"type":"marker","contents":"bagel bites"}]"#;
+        let diagnostic = diagnose(line);
+        assert_eq!(diagnostic.message(), "this action is malformed");
+        assert_eq!(diagnostic.column(), 13);
+        assert!(diagnostic.help().unwrap().contains("content"));
+    }
+}
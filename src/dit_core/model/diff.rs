@@ -0,0 +1,90 @@
+use super::super::Action;
+use super::{Book, Message};
+
+/// How two books compare, from [`Book::diff`].
+///
+/// Books are compared by message keys, so two books share every message up
+/// to the first one where the keys differ. Everything after that point is
+/// unique to one side or the other.
+pub struct BookDiff<'a, A: Action> {
+    left: &'a Book<A>,
+    right: &'a Book<A>,
+    shared: usize,
+}
+
+impl<'a, A: Action> BookDiff<'a, A> {
+    pub(crate) fn new(left: &'a Book<A>, right: &'a Book<A>) -> Self {
+        BookDiff {
+            left,
+            right,
+            shared: left.common_prefix(right),
+        }
+    }
+
+    /// How many messages both books start with.
+    pub fn shared(&self) -> usize {
+        self.shared
+    }
+
+    /// The last message both books have, or `None` if they have nothing in
+    /// common.
+    pub fn last_shared(&self) -> Option<&'a Message<A>> {
+        self.shared
+            .checked_sub(1)
+            .map(|index| &self.left.messages()[index])
+    }
+
+    /// The messages only in the left book.
+    pub fn left_only(&self) -> &'a [Message<A>] {
+        &self.left.messages()[self.shared..]
+    }
+
+    /// The messages only in the right book.
+    pub fn right_only(&self) -> &'a [Message<A>] {
+        &self.right.messages()[self.shared..]
+    }
+
+    pub fn left_state(&self) -> &'a A::State {
+        self.left.state()
+    }
+
+    pub fn right_state(&self) -> &'a A::State {
+        self.right.state()
+    }
+
+    /// Whether both books have exactly the same messages.
+    pub fn is_same(&self) -> bool {
+        self.left_only().is_empty() && self.right_only().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::mode_a::ActionA;
+    use super::super::{Book, Miner};
+
+    #[test]
+    fn diff_finds_messages_after_fork() {
+        let mut left = Book::<ActionA>::new();
+        left.append(ActionA::NoOp, &Miner::new()).unwrap();
+        let mut right = left.fork(0).unwrap();
+        left.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        right.append(ActionA::NoOp, &Miner::new()).unwrap();
+        right.append(ActionA::NoOp, &Miner::new()).unwrap();
+
+        let diff = left.diff(&right);
+        assert_eq!(diff.shared(), 1);
+        assert_eq!(
+            diff.last_shared().map(|msg| msg.key()),
+            Some(left.ledger().messages()[0].key())
+        );
+        assert_eq!(diff.left_only().len(), 1);
+        assert_eq!(diff.right_only().len(), 2);
+        assert_eq!(diff.left_state().version(), 200);
+        assert_eq!(diff.right_state().version(), 1_00);
+        assert!(!diff.is_same());
+        assert!(left.diff(&left).is_same());
+        assert!(Book::<ActionA>::new().diff(&left).last_shared().is_none());
+    }
+}
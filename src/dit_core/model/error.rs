@@ -1,5 +1,5 @@
-use super::super::Action;
-use super::{Message, Mode};
+use super::super::{Action, HashAlgorithm, LinkHasher};
+use super::{Diagnostic, HexString, Message, Mode, StopReason};
 use serde_json;
 use std::{fmt, io};
 
@@ -9,29 +9,153 @@ pub enum Error<A: Action> {
     IoError(String, io::Error),
     IoError2(io::Error),
     SerdeError(serde_json::Error),
+    InvalidLine(Diagnostic),
     FailedValidation {
         file_name: String,
         line_number: usize,
-        last_message: Message<A>,
-        failed_message: Message<A>,
+        last_message: Box<Message<A>>,
+        failed_message: Box<Message<A>>,
+    },
+    BrokenLink {
+        line_number: Option<usize>,
+        last_message: Box<Message<A>>,
+        failed_message: Box<Message<A>>,
+    },
+    BadSignature {
+        line_number: Option<usize>,
+        message: Box<Message<A>>,
+    },
+    WrongIdentity {
+        public_key: Option<HexString>,
+    },
+    Unsigned {
+        file_name: String,
+        line_number: usize,
+    },
+    ProblemsFound {
+        file_name: String,
+        problem_count: usize,
+    },
+    NoSuchMessage {
+        index: usize,
+        message_count: usize,
+    },
+    MergeConflict {
+        skipped: Vec<(usize, Error<A>)>,
+    },
+    BadAction {
+        action: A,
+        index: Option<usize>,
+        file_name: Option<String>,
+        line_number: Option<usize>,
+        reason: String,
+    },
+    UnterminatedHeader,
+    UnknownHashAlgorithm(String),
+    MiningStopped {
+        reason: StopReason,
+        attempts: u64,
     },
-    BadAction, // Impl better later
     WrongMode {
         mode: Mode,                // Mode of the file
         expected_modes: Vec<Mode>, // Mode of the
     },
 }
 
+impl<A: Action> Error<A> {
+    /// An error for an action that can't be applied, with a `reason` that
+    /// makes sense to the player.
+    pub fn bad_action<S: Into<String>>(action: A, reason: S) -> Self {
+        Error::BadAction {
+            action,
+            index: None,
+            file_name: None,
+            line_number: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// Records which message a [`BadAction`](Error::BadAction) was for, if
+    /// it doesn't say already. Other errors are left as they are.
+    pub fn at_message(self, message_index: usize) -> Self {
+        match self {
+            Error::BadAction {
+                index: None,
+                action,
+                file_name,
+                line_number,
+                reason,
+            } => Error::BadAction {
+                action,
+                index: Some(message_index),
+                file_name,
+                line_number,
+                reason,
+            },
+            err => err,
+        }
+    }
+
+    /// Records which line of a file a [`BadAction`](Error::BadAction) was
+    /// read from, if it doesn't say already. Other errors are left as they are.
+    pub fn on_line(self, line_number: usize) -> Self {
+        match self {
+            Error::BadAction {
+                line_number: None,
+                action,
+                index,
+                file_name,
+                reason,
+            } => Error::BadAction {
+                action,
+                index,
+                file_name,
+                line_number: Some(line_number),
+                reason,
+            },
+            err => err,
+        }
+    }
+}
+
 impl<A: Action> fmt::Display for Error<A> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::IoError(file_name, err) if err.kind() == io::ErrorKind::NotFound  => write!(f, "Um, sorry, but I can't find {}.", file_name.as_str()),
-            Error::FailedValidation {file_name, line_number, last_message, failed_message} => write!(f,"Welp, looks like this file, {}, is invalid. You've got a bad link on line {}.\nFrom {}\nTo-> {}", file_name, line_number, last_message, failed_message),
+            Error::FailedValidation {file_name, line_number, last_message, failed_message} => write!(f, "Welp, looks like this file, {}, is invalid.\n{}", file_name, Diagnostic::for_broken_link(*line_number, last_message, failed_message).with_file_name(file_name)),
+            Error::InvalidLine(diagnostic) => write!(f, "Hmm, I couldn't make sense of this line: {}", diagnostic),
+            Error::BrokenLink {line_number: Some(line_number), last_message, failed_message} => write!(f, "Whoa there, has this book been tampered with?\n{}", Diagnostic::for_broken_link(*line_number, last_message, failed_message)),
+            Error::BrokenLink {line_number: None, last_message, failed_message} => write!(f, "Whoa there, that message isn't linked to the end of the book. Was it mined for a different book?\nFrom {}\nTo-> {}", last_message, failed_message),
+            Error::BadSignature {line_number: Some(line_number), message} => write!(f, "Hey, the message on line {} is signed, but not by the player whose book this is.\n{}", line_number, message),
+            Error::BadSignature {line_number: None, message} => write!(f, "Hey, that message is signed, but not by the player whose book this is.\n{}", message),
+            Error::WrongIdentity {public_key: Some(public_key)} => write!(f, "Nice try, but this book belongs to another player, with the public key {}.", public_key),
+            Error::WrongIdentity {public_key: None} => write!(f, "Sorry, this book doesn't have a player's public key, so I can't sign messages in it."),
+            Error::Unsigned {file_name, line_number} => write!(f, "The links in {} hold, but the message on line {} isn't signed by the player whose book it is.", file_name, line_number),
+            Error::ProblemsFound {file_name, problem_count} => write!(f, "All in all, {} isn't valid. That's {} problems.", file_name, problem_count),
+            Error::NoSuchMessage {index, message_count} => write!(f, "Hmm, there's no message #{} here. This book only has {} messages.", index.saturating_add(1), message_count),
+            Error::MergeConflict {skipped} => write!(f, "Sorry, I couldn't merge these. These actions from their side don't apply on top of ours:{}", skipped.iter().map(|(index, err)| format!("\n  Their #{}: {}", index + 1, err)).collect::<String>()),
             Error::IoError(file_name, err) => write!(f, "Sorry, I tried reading {}, but I ran into a problem and got this error:\n{}", file_name, err),
             Error::IoError2(err) => write!(f, "Sorry, I tried reading this book, but I ran into a problem and got this error:\n{}", err),
             Error::SerdeError(err) => write!(f, "Dang it, I messed up. I ran into a serialization problem:\n{}", err),
-            Error::WrongMode {mode, expected_modes} => write!(f, "Mate, this file is in mode {:?}. We need it to be in one of these modes: {:?}", mode, expected_modes),
-            Error::BadAction => write!(f , "This error message is a work in progress, but an action did a bad"), // TODO fix this error message
+            Error::WrongMode {mode, expected_modes} => write!(f, "Mate, this file is in mode {}. We need it to be in one of these modes: {}", mode, expected_modes.iter().map(Mode::to_string).collect::<Vec<_>>().join(", ")),
+            Error::UnterminatedHeader => write!(f, "Hold on, this file has a header that never ends. Header lines should finish with a \"---\" line."),
+            Error::UnknownHashAlgorithm(name) => write!(f, "This book says its messages are linked with {}, but I don't know that hash algorithm. I know {:?}.", name, HashAlgorithm::ALL.iter().map(LinkHasher::name).collect::<Vec<_>>()),
+            Error::MiningStopped {reason: StopReason::Cancelled, attempts} => write!(f, "Alright, I stopped mining after {} attempts. Nothing was saved.", attempts),
+            Error::MiningStopped {reason: StopReason::DeadlinePassed, attempts} => write!(f, "I ran out of time after {} attempts without finding a key. Nothing was saved.", attempts),
+            Error::MiningStopped {reason: StopReason::AttemptLimitReached, attempts} => write!(f, "I gave up after {} attempts without finding a key. Nothing was saved.", attempts),
+            Error::MiningStopped {reason: StopReason::NoncesExhausted, attempts} => write!(f, "None of the {} keys I was given worked. Nothing was saved.", attempts),
+            Error::BadAction {action, index: Some(index), file_name, line_number, reason} => write!(f, "Sorry, {} can't be message #{}{}: {}", action.to_string(), index + 1, location(file_name.as_deref(), *line_number), reason),
+            Error::BadAction {action, index: None, file_name, line_number, reason} => write!(f, "Sorry, I can't apply {}{}: {}", action.to_string(), location(file_name.as_deref(), *line_number), reason),
         }
     }
 }
+
+/// Where in a file something went wrong, to go after what went wrong.
+fn location(file_name: Option<&str>, line_number: Option<usize>) -> String {
+    match (file_name, line_number) {
+        (Some(file_name), Some(line_number)) => format!(" (line {} of {})", line_number, file_name),
+        (Some(file_name), None) => format!(" (in {})", file_name),
+        (None, Some(line_number)) => format!(" (line {})", line_number),
+        (None, None) => String::new(),
+    }
+}
@@ -0,0 +1,204 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+/// How many attempts it takes on average to mine a message with `bit_cost`
+/// bits that have to match.
+///
+/// Each attempt has a 1 in 2<sup>`bit_cost`</sup> chance of working, so this
+/// is 2<sup>`bit_cost`</sup>.
+pub fn expected_attempts(bit_cost: usize) -> f64 {
+    2f64.powi(i32::try_from(bit_cost).unwrap_or(i32::MAX))
+}
+
+/// How long mining a message should take on a machine that makes
+/// `hashes_per_second` attempts a second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Estimate {
+    bit_cost: usize,
+    hashes_per_second: f64,
+}
+
+impl Estimate {
+    pub fn new(bit_cost: usize, hashes_per_second: f64) -> Self {
+        Estimate {
+            bit_cost,
+            hashes_per_second,
+        }
+    }
+
+    pub fn bit_cost(&self) -> usize {
+        self.bit_cost
+    }
+
+    pub fn hashes_per_second(&self) -> f64 {
+        self.hashes_per_second
+    }
+
+    /// See [`expected_attempts`].
+    pub fn expected_attempts(&self) -> f64 {
+        expected_attempts(self.bit_cost)
+    }
+
+    /// How long the expected number of attempts takes, or `None` if the hash
+    /// rate is 0.
+    pub fn expected_time(&self) -> Option<Duration> {
+        if self.hashes_per_second > 0.0 {
+            Some(Duration::from_secs_f64(
+                (self.expected_attempts() / self.hashes_per_second).min(u64::MAX as f64),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} bits have to match, which takes about {:.0} attempts. At {} hashes a second that's {}",
+            self.bit_cost,
+            self.expected_attempts(),
+            si(self.hashes_per_second),
+            describe(self.expected_time())
+        )
+    }
+}
+
+/// How mining is going, passed to the hook set with
+/// [`Miner::on_progress`](super::Miner::on_progress).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MiningStats {
+    attempts: u64,
+    elapsed: Duration,
+    bit_cost: usize,
+}
+
+impl MiningStats {
+    pub(crate) fn new(attempts: u64, elapsed: Duration, bit_cost: usize) -> Self {
+        MiningStats {
+            attempts,
+            elapsed,
+            bit_cost,
+        }
+    }
+
+    /// Attempts made so far, across all workers.
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    /// Time since mining started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn bit_cost(&self) -> usize {
+        self.bit_cost
+    }
+
+    /// Attempts a second since mining started.
+    pub fn hashes_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.attempts as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// How much longer mining is expected to take at the current hash rate.
+    ///
+    /// Every attempt is as likely to work as the last, so the work left is
+    /// always about [`expected_attempts`], however many attempts have been
+    /// made. This only changes as the hash rate does.
+    pub fn estimated_time_left(&self) -> Option<Duration> {
+        Estimate::new(self.bit_cost, self.hashes_per_second()).expected_time()
+    }
+}
+
+impl fmt::Display for MiningStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} attempts at {} hashes a second, {} left",
+            self.attempts,
+            si(self.hashes_per_second()),
+            describe(self.estimated_time_left())
+        )
+    }
+}
+
+/// A rough duration that reads well, like "about 3 minutes".
+fn describe(duration: Option<Duration>) -> String {
+    let seconds = match duration {
+        Some(duration) => duration.as_secs_f64(),
+        None => return String::from("who knows how long"),
+    };
+    let units = [
+        (60.0 * 60.0 * 24.0 * 365.0, "year"),
+        (60.0 * 60.0 * 24.0, "day"),
+        (60.0 * 60.0, "hour"),
+        (60.0, "minute"),
+        (1.0, "second"),
+    ];
+    match units.iter().find(|(size, _)| seconds >= *size) {
+        Some((size, unit)) => {
+            let count = (seconds / size).round();
+            let plural = if count == 1.0 { "" } else { "s" };
+            format!("about {} {}{}", count, unit, plural)
+        }
+        None => String::from("less than a second"),
+    }
+}
+
+/// A number with an SI prefix, like "1.2M".
+fn si(number: f64) -> String {
+    let prefixes = [(1e9, "G"), (1e6, "M"), (1e3, "k")];
+    match prefixes.iter().find(|(size, _)| number >= *size) {
+        Some((size, prefix)) => format!("{:.1}{}", number / size, prefix),
+        None => format!("{:.0}", number),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe, expected_attempts, Estimate, MiningStats};
+    use std::time::Duration;
+
+    #[test]
+    fn estimate_divides_attempts_by_rate() {
+        assert_eq!(expected_attempts(0), 1.0);
+        assert_eq!(expected_attempts(8), 256.0);
+        let estimate = Estimate::new(10, 512.0);
+        assert_eq!(estimate.expected_time(), Some(Duration::from_secs(2)));
+        assert_eq!(Estimate::new(10, 0.0).expected_time(), None);
+        assert!(estimate.to_string().contains("about 2 seconds"));
+    }
+
+    #[test]
+    fn stats_give_rate_and_time_left() {
+        let stats = MiningStats::new(3000, Duration::from_secs(2), 12);
+        assert_eq!(stats.hashes_per_second(), 1500.0);
+        let left = stats.estimated_time_left().unwrap().as_secs_f64();
+        assert!((left - 4096.0 / 1500.0).abs() < 1e-6);
+        assert_eq!(
+            stats.to_string(),
+            "3000 attempts at 1.5k hashes a second, about 3 seconds left"
+        );
+    }
+
+    #[test]
+    fn durations_read_well() {
+        assert_eq!(
+            describe(Some(Duration::from_millis(10))),
+            "less than a second"
+        );
+        assert_eq!(describe(Some(Duration::from_secs(60))), "about 1 minute");
+        assert_eq!(
+            describe(Some(Duration::from_secs(60 * 60 * 5))),
+            "about 5 hours"
+        );
+    }
+}
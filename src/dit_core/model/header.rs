@@ -0,0 +1,266 @@
+use super::super::{Action, HashAlgorithm, LinkHasher, State};
+use super::{Diagnostic, Error, HexString, Mode};
+use rand::{thread_rng, Rng};
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
+
+/// Marks the end of the header section of a file.
+const HEADER_END: &str = "---";
+
+/// The header key recording which [`HashAlgorithm`] links the messages.
+const HASH_KEY: &str = "hash";
+
+/// The header key recording the root hash that the first message links to.
+const ROOT_KEY: &str = "root";
+
+/// How many random bytes a new root hash has.
+const ROOT_HASH_BYTES: usize = 16;
+
+/// The header key recording the public key of the player whose book it is.
+const PUBLIC_KEY_KEY: &str = "public_key";
+
+/// The header key recording where a forked book branched off its parent.
+const PARENT_KEY: &str = "parent";
+
+/// The lines at the top of a file, before any messages.
+///
+/// The first line of a file is always the mode, and is followed by any number
+/// of header lines and then the `"---"` marker. Every line is written as a
+/// JSON string (quotes and all) so that each line of the file is valid JSON.
+///
+/// ```text
+/// "A"
+/// "some header line"
+/// "---"
+/// ["b5fb3141",{"type":"marker","content":"bagel bites"}]
+/// ```
+///
+/// Header lines of the form `key=value` are settings for the book as a whole,
+/// such as `hash=sha3-256` or the `root=` hash the first message links to.
+/// Every header line is also passed on to the book's [`State`].
+///
+/// Files written before headers existed start straight with messages. These
+/// are read as having no header at all.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    mode: Mode,
+    lines: Vec<String>,
+}
+
+impl Header {
+    pub fn new(mode: Mode) -> Self {
+        Header {
+            mode,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn with_line<S: Into<String>>(mut self, line: S) -> Self {
+        self.lines.push(line.into());
+        self
+    }
+
+    /// Adds a `key=value` setting line.
+    pub fn with_value(self, key: &str, value: &str) -> Self {
+        self.with_line(format!("{}={}", key, value))
+    }
+
+    /// Finds the value of the first `key=value` line for `key`.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| {
+            let (line_key, value) = line.split_once('=')?;
+            if line_key == key {
+                Some(value)
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn with_hash_algorithm(self, algorithm: HashAlgorithm) -> Self {
+        self.with_value(HASH_KEY, algorithm.name())
+    }
+
+    /// The hash algorithm used to link messages in this book. Books that
+    /// don't record one use [`HashAlgorithm::default()`].
+    pub fn hash_algorithm<A: Action>(&self) -> Result<HashAlgorithm, Error<A>> {
+        match self.value(HASH_KEY) {
+            Some(name) => HashAlgorithm::from_name(name)
+                .ok_or_else(|| Error::UnknownHashAlgorithm(String::from(name))),
+            None => Ok(HashAlgorithm::default()),
+        }
+    }
+
+    /// Records the root hash of the book, which the first message has to be
+    /// linked to. See [`State::root_hash`].
+    pub fn with_root_hash(self, root_hash: &HexString) -> Self {
+        self.with_value(ROOT_KEY, &root_hash.to_string())
+    }
+
+    /// Records a new random root hash, for a book that is just being created.
+    pub fn with_new_root_hash(self) -> Self {
+        let root_hash: [u8; ROOT_HASH_BYTES] = thread_rng().gen();
+        self.with_root_hash(&HexString::from(&root_hash[..]))
+    }
+
+    /// The root hash recorded in this header, if there is one. Books written
+    /// before root hashes existed don't have one.
+    pub fn root_hash(&self) -> Option<HexString> {
+        HexString::parse(self.value(ROOT_KEY)?)
+    }
+
+    /// Records the public key of the player whose book this is, which every
+    /// signed message is checked against. See [`Identity`](super::Identity).
+    pub fn with_public_key(self, public_key: &HexString) -> Self {
+        self.with_value(PUBLIC_KEY_KEY, &public_key.to_string())
+    }
+
+    /// The public key of the player whose book this is, if it has one.
+    pub fn public_key(&self) -> Option<HexString> {
+        HexString::parse(self.value(PUBLIC_KEY_KEY)?)
+    }
+
+    /// Records that this book was forked from a parent book, sharing its
+    /// first `shared` messages, the last of which has the key `key`.
+    ///
+    /// Replaces any parent already recorded, so a fork of a fork only points
+    /// at the book it was forked from.
+    pub fn with_parent(mut self, shared: usize, key: &HexString) -> Self {
+        let prefix = format!("{}=", PARENT_KEY);
+        self.lines.retain(|line| !line.starts_with(prefix.as_str()));
+        self.with_value(PARENT_KEY, &format!("{}:{}", shared, key))
+    }
+
+    /// How many messages this book shares with the book it was forked from,
+    /// and the key of the last one, if it is a fork.
+    pub fn parent(&self) -> Option<(usize, HexString)> {
+        let (shared, key) = self.value(PARENT_KEY)?.split_once(':')?;
+        Some((shared.parse().ok()?, HexString::parse(key)?))
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines[..]
+    }
+
+    /// How many lines of the file this header takes up.
+    pub fn line_count(&self) -> usize {
+        self.lines.len() + 2
+    }
+
+    /// Builds the starting state for a book with this header.
+    pub fn initial_state<S: State>(&self) -> S {
+        self.lines
+            .iter()
+            .fold(S::default(), |state, line| state.read_header_line(line))
+    }
+
+    /// Checks that this header is for the mode `A` is written for.
+    pub fn check_mode<A: Action>(&self) -> Result<(), Error<A>> {
+        let expected = A::State::mode();
+        if self.mode == expected {
+            Ok(())
+        } else {
+            Err(Error::WrongMode {
+                mode: self.mode,
+                expected_modes: vec![expected],
+            })
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{}", to_json(&self.mode)?)?;
+        for line in self.lines.iter() {
+            writeln!(writer, "{}", to_json(line)?)?;
+        }
+        writeln!(writer, "{}", to_json(&HEADER_END)?)
+    }
+
+    /// Finds the mode of a book from its first line, without reading the rest.
+    ///
+    /// Returns `None` if the book doesn't start with a mode, either because it
+    /// was written before books had headers or because it can't be read.
+    pub fn read_mode<R: BufRead>(reader: R) -> Option<Mode> {
+        let first_line = reader.lines().next()?.ok()?;
+        serde_json::from_str(first_line.as_str()).ok()
+    }
+
+    /// Reads the header from the start of a file, leaving `lines` at the first
+    /// message.
+    ///
+    /// Returns `None` without consuming anything if the file doesn't start
+    /// with a header.
+    pub fn read_from<A, I>(lines: &mut Peekable<I>) -> Result<Option<Header>, Error<A>>
+    where
+        A: Action,
+        I: Iterator<Item = io::Result<String>>,
+    {
+        let has_header = match lines.peek() {
+            Some(Ok(line)) => serde_json::from_str::<String>(line.as_str()).is_ok(),
+            _ => false,
+        };
+        if !has_header {
+            return Ok(None);
+        }
+        let mode_line = lines.next().unwrap().map_err(Error::IoError2)?;
+        let mode: Mode = serde_json::from_str(mode_line.as_str()).map_err(|_| {
+            Error::InvalidLine(
+                Diagnostic::whole_line(1, &mode_line, "this isn't a mode I know")
+                    .with_help("the first line should be the mode of the book, like \"A\""),
+            )
+        })?;
+        let mut header = Header::new(mode);
+        for line_number in 2.. {
+            let line = lines
+                .next()
+                .ok_or(Error::UnterminatedHeader)?
+                .map_err(Error::IoError2)?;
+            let value: String = serde_json::from_str(line.as_str()).map_err(|_| {
+                Error::InvalidLine(
+                    Diagnostic::whole_line(line_number, &line, "this header line isn't a string")
+                        .with_help(
+                        "header lines are JSON strings, and the header ends with a \"---\" line",
+                    ),
+                )
+            })?;
+            if value == HEADER_END {
+                break;
+            }
+            header.lines.push(value);
+        }
+        Ok(Some(header))
+    }
+}
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> io::Result<String> {
+    serde_json::to_string(value).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{HexString, Mode};
+    use super::Header;
+
+    #[test]
+    fn with_parent_replaces_parent() {
+        let key = HexString::from(&[1, 2, 3, 4][..]);
+        let header = Header::new(Mode::A)
+            .with_line("hi")
+            .with_parent(3, &HexString::default())
+            .with_parent(5, &key);
+        assert_eq!(header.parent(), Some((5, key)));
+        assert_eq!(header.lines(), ["hi", "parent=5:01020304"]);
+        assert_eq!(Header::new(Mode::A).parent(), None);
+    }
+
+    #[test]
+    fn read_mode_reads_first_line() {
+        assert_eq!(Header::read_mode(&b"\"N\"\n\"---\"\n"[..]), Some(Mode::N));
+        assert_eq!(Header::read_mode(&b"[\"b5fb3141\",{}]\n"[..]), None);
+        assert_eq!(Header::read_mode(&b""[..]), None);
+    }
+}
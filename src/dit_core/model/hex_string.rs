@@ -1,8 +1,11 @@
-use serde::{Deserialize, Serialize};
+use serde::de::{Error, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
 
 /// A wrapper for a string of hexadecimal characters
-/// There is no way to initialize it with a string of other characters other than deserializing tampered data, so it should be safe to deserialize
-#[derive(Clone, Serialize, Deserialize, Debug)]
+/// There is no way to initialize it with a string of other characters, since
+/// deserializing checks the characters too.
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
 #[serde(transparent)]
 pub struct HexString(String);
 
@@ -10,6 +13,36 @@ impl HexString {
     pub fn to_bytes(&self) -> Vec<u8> {
         Vec::from(self)
     }
+
+    /// Reads a string of hex digits, or `None` if `string` isn't one.
+    pub fn parse(string: &str) -> Option<HexString> {
+        if HexString::is_valid(string) {
+            Some(HexString(string.to_ascii_lowercase()))
+        } else {
+            None
+        }
+    }
+
+    /// Whether `string` is a non-empty, even number of hex digits.
+    pub(crate) fn is_valid(string: &str) -> bool {
+        !string.is_empty()
+            && string.len().is_multiple_of(2)
+            && string.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+impl<'de> Deserialize<'de> for HexString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        if HexString::is_valid(&string) {
+            Ok(HexString(string))
+        } else {
+            Err(D::Error::invalid_value(
+                Unexpected::Str(&string),
+                &"an even number of hex digits",
+            ))
+        }
+    }
 }
 
 impl From<&[u8]> for HexString {
@@ -24,9 +57,9 @@ impl From<&HexString> for Vec<u8> {
     }
 }
 
-impl ToString for HexString {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl fmt::Display for HexString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
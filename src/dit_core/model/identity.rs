@@ -0,0 +1,115 @@
+use super::super::Action;
+use super::{HexString, Message};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::thread_rng;
+use std::convert::{TryFrom, TryInto};
+
+/// A player's Ed25519 key pair, for signing the messages they add to a book.
+///
+/// The public key goes in the header of the player's books, and each message
+/// they add carries a signature of its key and action. The secret key should
+/// stay on the player's computer.
+#[derive(Clone)]
+pub struct Identity(SigningKey);
+
+impl Identity {
+    /// Creates a new random identity.
+    pub fn generate() -> Self {
+        Identity(SigningKey::generate(&mut thread_rng()))
+    }
+
+    /// Reads an identity back from its [`secret_key`](Identity::secret_key),
+    /// or `None` if `secret_key` isn't the right length.
+    pub fn from_secret_key(secret_key: &HexString) -> Option<Self> {
+        let bytes = secret_key.to_bytes().try_into().ok()?;
+        Some(Identity(SigningKey::from_bytes(&bytes)))
+    }
+
+    pub fn secret_key(&self) -> HexString {
+        HexString::from(&self.0.to_bytes()[..])
+    }
+
+    pub fn public_key(&self) -> HexString {
+        HexString::from(&self.0.verifying_key().to_bytes()[..])
+    }
+
+    /// Signs the key and action of `message`.
+    pub fn sign<A: Action>(&self, message: &Message<A>) -> HexString {
+        let signature = self.0.sign(&message.signed_bytes());
+        HexString::from(&signature.to_bytes()[..])
+    }
+}
+
+/// What checking the signature of a message found.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SignatureCheck {
+    /// The message has no signature.
+    Unsigned,
+    /// The message was signed with the key in the header.
+    Valid,
+    /// The message has a signature, but not one from the key in the header.
+    /// This includes books that have no key in the header at all.
+    Invalid,
+}
+
+/// Checks the signature of `message` against the `public_key` from a header.
+pub(crate) fn check_signature<A: Action>(
+    public_key: Option<&HexString>,
+    message: &Message<A>,
+) -> SignatureCheck {
+    let signature = match message.signature() {
+        Some(signature) => signature,
+        None => return SignatureCheck::Unsigned,
+    };
+    let verified = public_key.is_some_and(|public_key| {
+        let public_key = VerifyingKey::try_from(&public_key.to_bytes()[..]);
+        let signature = Signature::from_slice(&signature.to_bytes());
+        match (public_key, signature) {
+            (Ok(public_key), Ok(signature)) => public_key
+                .verify(&message.signed_bytes(), &signature)
+                .is_ok(),
+            _ => false,
+        }
+    });
+    if verified {
+        SignatureCheck::Valid
+    } else {
+        SignatureCheck::Invalid
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::mode_a::ActionA;
+    use super::super::Message;
+    use super::{check_signature, Identity, SignatureCheck};
+
+    #[test]
+    fn signatures_only_match_their_key() {
+        let identity = Identity::generate();
+        let other = Identity::generate();
+        let message = Message::<ActionA>::default();
+        let signed = message.clone().with_signature(identity.sign(&message));
+        let public_key = identity.public_key();
+        assert_eq!(
+            check_signature(Some(&public_key), &signed),
+            SignatureCheck::Valid
+        );
+        assert_eq!(
+            check_signature(Some(&other.public_key()), &signed),
+            SignatureCheck::Invalid
+        );
+        assert_eq!(check_signature(None, &signed), SignatureCheck::Invalid);
+        assert_eq!(
+            check_signature(Some(&public_key), &message),
+            SignatureCheck::Unsigned
+        );
+    }
+
+    #[test]
+    fn identity_survives_secret_key() {
+        let identity = Identity::generate();
+        let read = Identity::from_secret_key(&identity.secret_key()).unwrap();
+        assert_eq!(read.public_key(), identity.public_key());
+    }
+}
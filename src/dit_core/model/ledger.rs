@@ -1,66 +1,282 @@
-use super::super::Action;
+use super::super::{Action, HashAlgorithm};
 use super::{HexString, Message};
-use std::iter::FromIterator;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sha3::{Digest, Sha3_256};
+
+/// The random number generator handed out by [`PendingLedger::rng`].
+///
+/// ChaCha is used since its output for a given seed is fixed, so an action
+/// rolls the same numbers whether it is being mined or validated years later.
+pub type LedgerRng = ChaCha20Rng;
+
+/// Which message keys are used to seed randomness for an action.
+///
+/// Each source has a different tradeoff for how easily a player can reroll a
+/// result they don't like:
+///
+/// * [`CurrentKey`](RandomSource::CurrentKey) changes every time the action is
+///   mined, so a player can reroll by mining again, paying the bit cost each
+///   time. Good for "keep trying until you succeed" actions.
+/// * [`PreviousKey`](RandomSource::PreviousKey) is fixed once the previous
+///   message exists, so rerolling means undoing back past it. Beware that a
+///   player could still add free messages (like markers) before the action to
+///   change the result.
+/// * [`KeyBack`](RandomSource::KeyBack) uses a specific earlier message, so
+///   the player has to undo all the way back to it to get a different result.
+/// * [`Combined`](RandomSource::Combined) mixes several sources together.
+///   This doesn't usually combine the benefits of each.
+///
+/// Keys are hashed before being used as a seed, so the bits shared between a
+/// key and the key before it (thanks to the proof of work) don't make the
+/// results any less random.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RandomSource {
+    /// The key of the message the action is being applied with.
+    CurrentKey,
+    /// The key of the message right before the action.
+    PreviousKey,
+    /// The key of the message `n` messages before the action, so `KeyBack(0)`
+    /// is the same as `CurrentKey` and `KeyBack(1)` the same as `PreviousKey`.
+    /// Going back past the first message gives the book's root hash.
+    KeyBack(usize),
+    /// All of these sources together, in order.
+    Combined(Vec<RandomSource>),
+}
 
 #[derive(Clone)]
-pub struct Ledger<'a, A: Action>(&'a [Message<A>]);
+pub struct Ledger<'a, A: Action> {
+    messages: &'a [Message<A>],
+    root_hash: Option<&'a HexString>,
+}
 
-pub struct PendingLedger<'a, A: Action>(&'a [Message<A>], &'a HexString);
+pub struct PendingLedger<'a, A: Action>(Ledger<'a, A>, &'a HexString);
 
 impl<'a, A: Action> Ledger<'a, A> {
     const EMPTY_MESSAGES: [Message<A>; 0] = [];
 
     pub fn new() -> Self {
-        Ledger(&Ledger::<A>::EMPTY_MESSAGES[..])
+        Ledger::from(&Ledger::<A>::EMPTY_MESSAGES[..])
+    }
+
+    /// Sets the root hash of the book the messages are from. Without one,
+    /// the root hash is [`HexString::default()`], as in books from before
+    /// root hashes.
+    pub fn with_root_hash(mut self, root_hash: &'a HexString) -> Self {
+        self.root_hash = Some(root_hash);
+        self
     }
 
     pub fn messages(&self) -> &[Message<A>] {
-        self.0
+        self.messages
+    }
+
+    pub fn root_hash(&self) -> HexString {
+        self.root_hash.cloned().unwrap_or_default()
     }
 
     pub fn with_hash(&self, next_hash: &'a HexString) -> PendingLedger<'a, A> {
-        PendingLedger(self.0, next_hash)
+        PendingLedger(self.clone(), next_hash)
     }
 }
 
 impl<'a, A: Action> PendingLedger<'a, A> {
     pub fn messages(&self) -> &[Message<A>] {
-        self.0
+        self.0.messages
     }
 
     pub fn next_hash(&self) -> &HexString {
         self.1
     }
+
+    /// The key of the message `n` messages before the action being applied.
+    ///
+    /// See [`RandomSource::KeyBack`].
+    pub fn key_back(&self, n: usize) -> HexString {
+        match n {
+            0 => self.1.clone(),
+            n => self
+                .messages()
+                .len()
+                .checked_sub(n)
+                .map_or_else(|| self.0.root_hash(), |i| self.messages()[i].key().clone()),
+        }
+    }
+
+    /// A seed derived only from the keys picked out by `source`.
+    pub fn seed(&self, source: &RandomSource) -> [u8; 32] {
+        let mut hasher = Sha3_256::new();
+        match source {
+            RandomSource::CurrentKey => hasher.update(self.key_back(0).to_bytes()),
+            RandomSource::PreviousKey => hasher.update(self.key_back(1).to_bytes()),
+            RandomSource::KeyBack(n) => hasher.update(self.key_back(*n).to_bytes()),
+            RandomSource::Combined(sources) => sources
+                .iter()
+                .for_each(|source| hasher.update(self.seed(source))),
+        }
+        hasher.finalize().into()
+    }
+
+    /// A random number generator that gives the same numbers every time the
+    /// action is applied with the same keys.
+    pub fn rng(&self, source: &RandomSource) -> LedgerRng {
+        LedgerRng::from_seed(self.seed(source))
+    }
 }
 
 impl<'a, A: Action> From<&'a [Message<A>]> for Ledger<'a, A> {
     fn from(messages: &'a [Message<A>]) -> Self {
-        Ledger(messages)
+        Ledger {
+            messages,
+            root_hash: None,
+        }
     }
 }
 
-/*
-impl <'a, A: Action> From<Vec<Message<A>>> for Ledger<'a, A> {
-    fn from(vec: Vec<Message<A>>) -> Self {
-        Ledger(&vec[..], None)
+/// An owned [`Ledger`], for when the messages can't be borrowed from a
+/// [`Book`](super::Book), such as when they're returned from
+/// [`read_state`](super::super::read_state).
+#[derive(Clone, Debug)]
+pub struct LedgerBuf<A: Action> {
+    messages: Vec<Message<A>>,
+    root_hash: HexString,
+    hash_algorithm: HashAlgorithm,
+}
+
+impl<A: Action> LedgerBuf<A> {
+    pub fn new() -> Self {
+        LedgerBuf::from(Vec::new())
+    }
+
+    /// Sets the root hash of the book the messages are from, which the first
+    /// message is linked to.
+    pub fn with_root_hash(mut self, root_hash: HexString) -> Self {
+        self.root_hash = root_hash;
+        self
+    }
+
+    /// Sets the hash algorithm the messages are linked with, see
+    /// [`Header::hash_algorithm`](super::Header::hash_algorithm).
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    pub fn ledger(&self) -> Ledger<'_, A> {
+        Ledger::from(&self.messages[..]).with_root_hash(&self.root_hash)
+    }
+
+    pub fn messages(&self) -> &[Message<A>] {
+        &self.messages[..]
+    }
+
+    /// The last message, which the next message has to link to. With no
+    /// messages, this is the [genesis](Message::genesis) message.
+    pub fn last_message(&self) -> Message<A> {
+        self.messages
+            .last()
+            .cloned()
+            .unwrap_or_else(|| Message::genesis(self.root_hash.clone()))
+    }
+
+    pub fn into_messages(self) -> Vec<Message<A>> {
+        self.messages
     }
 }
 
-impl <'a, A: Action> From<&'a Vec<Message<A>>> for Ledger<'a, A> {
-    fn from(vec: &'a Vec<Message<A>>) -> Self {
-        Ledger(vec, None)
+impl<A: Action> From<Vec<Message<A>>> for LedgerBuf<A> {
+    fn from(messages: Vec<Message<A>>) -> Self {
+        LedgerBuf {
+            messages,
+            root_hash: HexString::default(),
+            hash_algorithm: HashAlgorithm::default(),
+        }
     }
 }
 
-impl <'a, A: Action> FromIterator<Message<A>> for Ledger<'a, A> {
-    fn from_iter<I: IntoIterator<Item=Message<A>>> (iter: I) -> Ledger<'a, A> {
-        Ledger(&iter.into_iter().collect::<Vec<_>>(), None)
+impl<A: Action> Default for LedgerBuf<A> {
+    fn default() -> Self {
+        LedgerBuf::new()
     }
 }
-*/
 
 impl<'a, A: Action> Default for Ledger<'a, A> {
     fn default() -> Ledger<'a, A> {
         Ledger::new()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::mode_a::ActionA;
+    use super::super::{HexString, Message};
+    use super::{Ledger, RandomSource};
+    use rand::Rng;
+
+    fn messages() -> Vec<Message<ActionA>> {
+        let state = Default::default();
+        let first = Message::default().gen_next_message(ActionA::NoOp, &Ledger::new(), &state);
+        let ledger = [first.clone()];
+        let second = first.gen_next_message(ActionA::NoOp, &Ledger::from(&ledger[..]), &state);
+        vec![first, second]
+    }
+
+    #[test]
+    fn rng_is_the_same_on_replay() {
+        let messages = messages();
+        let key = HexString::from(&[1, 2, 3, 4][..]);
+        let ledger = Ledger::from(&messages[..]);
+        let source =
+            RandomSource::Combined(vec![RandomSource::CurrentKey, RandomSource::KeyBack(2)]);
+        let first_rolls: Vec<u32> = ledger
+            .with_hash(&key)
+            .rng(&source)
+            .sample_iter(rand::distributions::Standard)
+            .take(5)
+            .collect();
+        let second_rolls: Vec<u32> = ledger
+            .with_hash(&key)
+            .rng(&source)
+            .sample_iter(rand::distributions::Standard)
+            .take(5)
+            .collect();
+        assert_eq!(first_rolls, second_rolls);
+    }
+
+    #[test]
+    fn key_back_counts_back_from_current_key() {
+        let messages = messages();
+        let key = HexString::from(&[1, 2, 3, 4][..]);
+        let root_hash = HexString::from(&[5, 6, 7, 8][..]);
+        let ledger = Ledger::from(&messages[..]).with_root_hash(&root_hash);
+        let pending = ledger.with_hash(&key);
+        assert_eq!(pending.key_back(0), key);
+        assert_eq!(&pending.key_back(1), messages[1].key());
+        assert_eq!(&pending.key_back(2), messages[0].key());
+        assert_eq!(pending.key_back(3), root_hash);
+        assert_eq!(
+            Ledger::from(&messages[..]).with_hash(&key).key_back(3),
+            HexString::default()
+        );
+        assert_eq!(
+            pending.seed(&RandomSource::KeyBack(1)),
+            pending.seed(&RandomSource::PreviousKey)
+        );
+    }
+
+    #[test]
+    fn different_sources_give_different_seeds() {
+        let messages = messages();
+        let key = HexString::from(&[1, 2, 3, 4][..]);
+        let ledger = Ledger::from(&messages[..]);
+        let pending = ledger.with_hash(&key);
+        assert_ne!(
+            pending.seed(&RandomSource::CurrentKey),
+            pending.seed(&RandomSource::PreviousKey)
+        );
+    }
+}
@@ -1,30 +1,63 @@
-use super::super::bit_match;
-use super::super::Action;
-use super::HexString;
+use super::super::{bit_match, stretch_key};
+use super::super::{Action, HashAlgorithm, LinkHasher};
+use super::{HexString, Ledger, Miner};
 
-use rand::{thread_rng, Rng};
+use digest::DynDigest;
 use serde::{Deserialize, Serialize};
 use serde_json;
-use sha3::{Digest, Sha3_224};
-use std::{fmt, fmt::Display, iter};
+use std::{fmt, fmt::Display};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
-#[serde(bound = "A: Action", from = "(HexString, A)", into = "(HexString, A)")]
+#[serde(bound = "A: Action", from = "MessageLine<A>", into = "MessageLine<A>")]
 pub struct Message<A: Action> {
     key: HexString,
     action: A,
+    signature: Option<HexString>,
 }
-/// Deserialize it from a tuple
+
+/// How a message is written in a file: its key and action, followed by its
+/// signature if it is signed. Files written before signatures existed only
+/// have the key and action.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "A: Action", untagged)]
+enum MessageLine<A: Action> {
+    Signed(HexString, A, HexString),
+    Unsigned(HexString, A),
+}
+
+impl<A: Action> From<MessageLine<A>> for Message<A> {
+    fn from(line: MessageLine<A>) -> Message<A> {
+        match line {
+            MessageLine::Signed(key, action, signature) => Message {
+                key,
+                action,
+                signature: Some(signature),
+            },
+            MessageLine::Unsigned(key, action) => Message::new(key, action),
+        }
+    }
+}
+
+impl<A: Action> From<Message<A>> for MessageLine<A> {
+    fn from(message: Message<A>) -> MessageLine<A> {
+        match message.signature {
+            Some(signature) => MessageLine::Signed(message.key, message.action, signature),
+            None => MessageLine::Unsigned(message.key, message.action),
+        }
+    }
+}
+
+/// An unsigned message from its key and action
 impl<A: Action> From<(HexString, A)> for Message<A> {
     fn from((key, action): (HexString, A)) -> Message<A> {
-        Message { key, action }
+        Message::new(key, action)
     }
 }
 
-/// Serialize it from a tuple
-impl<A: Action> Into<(HexString, A)> for Message<A> {
-    fn into(self) -> (HexString, A) {
-        (self.key, self.action)
+/// The key and action of a message, leaving out any signature
+impl<A: Action> From<Message<A>> for (HexString, A) {
+    fn from(message: Message<A>) -> (HexString, A) {
+        (message.key, message.action)
     }
 }
 
@@ -38,93 +71,113 @@ impl<A: Action> Message<A> {
         &self.key
     }
 
-    fn get_hasher_for_payload(&self, action: &A) -> Sha3_224 {
-        let mut hasher = Sha3_224::new();
-        hasher.update(self.key.to_bytes());
-        hasher.update(serde_json::to_string(action).expect("Issue serializing action"));
-        hasher
+    /// The message before the first message of a book, whose key is the
+    /// book's [`root_hash`](super::super::State::root_hash).
+    pub fn genesis(root_hash: HexString) -> Self {
+        Message::new(root_hash, A::default())
+    }
+
+    /// The signature of the player who added this message, if they signed it.
+    /// See [`Identity`](super::Identity).
+    pub fn signature(&self) -> Option<&HexString> {
+        self.signature.as_ref()
+    }
+
+    pub fn with_signature(mut self, signature: HexString) -> Self {
+        self.signature = Some(signature);
+        self
+    }
+
+    pub(crate) fn new(key: HexString, action: A) -> Self {
+        Message {
+            key,
+            action,
+            signature: None,
+        }
+    }
+
+    /// What a signature of this message signs: its key and action.
+    pub(crate) fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.key.to_bytes();
+        bytes.extend(
+            serde_json::to_string(&self.action)
+                .expect("Issue serializing action")
+                .as_bytes(),
+        );
+        bytes
+    }
+
+    pub(crate) fn get_hasher_for_payload(
+        &self,
+        hasher: &dyn LinkHasher,
+        action: &A,
+    ) -> Box<dyn DynDigest + Send + Sync> {
+        let mut digest = hasher.new_digest();
+        digest.update(&self.key.to_bytes());
+        digest.update(
+            serde_json::to_string(action)
+                .expect("Issue serializing action")
+                .as_bytes(),
+        );
+        digest
     }
 
-    /// Checks whether this message and the next message are validly linked.
+    /// Checks whether this message and the next message are validly linked
+    /// with the default hash algorithm.
     ///
-    /// The state is necessary as we might need that to determine the bit cost
-    /// for an action.
-    pub fn accepts_next_message(&self, next_message: &Message<A>, state: &A::State) -> bool {
-        let mut hasher = self.get_hasher_for_payload(&next_message.action);
-        hasher.update(&next_message.key.to_bytes());
-        let threshold = next_message.action.bit_cost(state);
+    /// The ledger and state are necessary as we might need them to determine
+    /// the bit cost for an action. The ledger holds the messages up to and
+    /// including this one.
+    pub fn accepts_next_message(
+        &self,
+        next_message: &Message<A>,
+        ledger: &Ledger<A>,
+        state: &A::State,
+    ) -> bool {
+        self.accepts_next_message_with(&HashAlgorithm::default(), next_message, ledger, state)
+    }
+
+    /// Checks whether this message and the next message are validly linked
+    /// with `hasher`.
+    pub fn accepts_next_message_with(
+        &self,
+        hasher: &dyn LinkHasher,
+        next_message: &Message<A>,
+        ledger: &Ledger<A>,
+        state: &A::State,
+    ) -> bool {
+        let mut digest = self.get_hasher_for_payload(hasher, &next_message.action);
+        digest.update(&next_message.key.to_bytes());
+        let threshold = next_message.action.bit_cost(ledger, state);
         bit_match(
             threshold,
-            Vec::from(&self.key).as_slice(),
-            &hasher.finalize(),
+            &stretch_key(&self.key.to_bytes(), threshold),
+            &digest.finalize(),
         )
     }
 
     /// Generate a message that can follow this one for the specified action.
-    pub fn gen_next_message_with_hook<F: Fn(HexString) -> ()>(
+    ///
+    /// The hook is called with the hash of every `hook_frequency`th attempt.
+    /// Use a [`Miner`](super::Miner) to configure the work further.
+    pub fn gen_next_message_with_hook<F: Fn(HexString) + Send + Sync>(
         &self,
         action: A,
+        ledger: &Ledger<A>,
         state: &A::State,
         hook: F,
         hook_frequency: u32,
     ) -> Self {
-        let hasher = self.get_hasher_for_payload(&action);
-        let threshold = action.bit_cost(state);
-        let prev_hash_bytes = self.key.to_bytes();
-        let mut rng = thread_rng(); // Might need to pass to the function to enable reproducible testing
-        let (key, _) = iter::repeat_with(|| rng.gen::<u32>())
-            .map(|n| n.to_le_bytes())
-            .zip((0..hook_frequency).cycle())
-            .skip_while(|(key, cycle_count)| {
-                let mut key_hasher = hasher.clone();
-                key_hasher.update(key);
-                // Might want some logic for breaking out of the program here.
-                let hash = key_hasher.finalize();
-                if *cycle_count == 0 {
-                    hook(HexString::from(&hash[..]));
-                }
-                !bit_match(threshold, &prev_hash_bytes, &hash)
-            })
-            .next()
-            .unwrap();
-
-        // Convert previous hash to bytes
-        // Sha digest the message.
-        // Generate random hashes. If we use a u32, we can make hashes of length 32 bits. Would we ever want one with more?
-        // Take the random hashes until one matches with threshold amount of bits at the end.
-        Message {
-            key: HexString::from(&key[..]),
-            action,
-        }
+        Miner::new()
+            .on_iter(hook)
+            .with_period(hook_frequency)
+            .mine(self, action, ledger, state)
+            .unwrap_or_else(|_| unreachable!("Mining without limits only stops with a key"))
     }
 
     /// Generate a message that can follow this one for the specified action.
-    pub fn gen_next_message(&self, action: A, state: &A::State) -> Self {
-        let hasher = self.get_hasher_for_payload(&action);
-        let threshold = action.bit_cost(state);
-        let prev_hash_bytes = self.key.to_bytes();
-        let mut rng = thread_rng(); // Might need to pass to the function to enable reproducible testing
-
-        let key = iter::repeat_with(|| rng.gen::<u32>())
-            .map(|n| n.to_le_bytes())
-            .skip_while(|key| {
-                let mut key_hasher = hasher.clone();
-                key_hasher.update(key);
-                // Might want some logic for breaking out of the program here.
-                let hash = key_hasher.finalize();
-                !bit_match(threshold, &prev_hash_bytes, &hash)
-            })
-            .next()
-            .unwrap();
-
-        // Convert previous hash to bytes
-        // Sha digest the message.
-        // Generate random hashes. If we use a u32, we can make hashes of length 32 bits. Would we ever want one with more?
-        // Take the random hashes until one matches with threshold amount of bits at the end.
-        Message {
-            key: HexString::from(&key[..]),
-            action,
-        }
+    pub fn gen_next_message(&self, action: A, ledger: &Ledger<A>, state: &A::State) -> Self {
+        self.gen_next_message_with_hook(action, ledger, state, |_| {}, 0)
     }
 }
 
@@ -136,9 +189,6 @@ impl<A: Action> Display for Message<A> {
 
 impl<A: Action> Default for Message<A> {
     fn default() -> Self {
-        Message {
-            key: HexString::default(),
-            action: A::default(),
-        }
+        Message::new(HexString::default(), A::default())
     }
 }
@@ -0,0 +1,612 @@
+use super::super::{bit_match, key_width, stretch_key};
+use super::super::{Action, HashAlgorithm, LinkHasher};
+use super::{Error, HexString, Ledger, Message, MiningStats};
+
+use rand::rngs::ThreadRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many attempts each worker makes between checking the clock.
+const DEADLINE_CHECK_PERIOD: u64 = 1024;
+
+/// A handle that can stop mining from another thread (or a signal handler).
+///
+/// Clones share the same flag, so cancelling any clone cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why mining stopped without finding a key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Cancelled,
+    DeadlinePassed,
+    AttemptLimitReached,
+    /// Every key from a [`NonceSource::List`] was tried.
+    NoncesExhausted,
+}
+
+/// Where the miner gets the keys it tries.
+///
+/// Every source except [`Random`](NonceSource::Random) gives the same series
+/// of keys every time. With these, the miner returns the first key in the
+/// series that works, no matter how many threads it uses, so the exact key
+/// of a message can be reproduced in tests or bug reports.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum NonceSource {
+    /// Random keys that are different every time.
+    #[default]
+    Random,
+    /// Random-looking keys from a generator seeded with this number.
+    Seeded(u64),
+    /// Counts up from this number, written as a little-endian key.
+    Sequential(u64),
+    /// Exactly these keys, in order. Mining stops with
+    /// [`StopReason::NoncesExhausted`] if none of them work.
+    List(Vec<Vec<u8>>),
+}
+
+/// A worker's view of a [`NonceSource`].
+enum Nonces<'a> {
+    Random(ThreadRng),
+    Seeded(Box<ChaCha20Rng>),
+    Sequential(u64),
+    List(&'a [Vec<u8>]),
+}
+
+impl<'a> Nonces<'a> {
+    fn new(source: &'a NonceSource) -> Self {
+        match source {
+            NonceSource::Random => Nonces::Random(thread_rng()),
+            NonceSource::Seeded(seed) => {
+                Nonces::Seeded(Box::new(ChaCha20Rng::seed_from_u64(*seed)))
+            }
+            NonceSource::Sequential(start) => Nonces::Sequential(*start),
+            NonceSource::List(keys) => Nonces::List(&keys[..]),
+        }
+    }
+
+    /// Writes the `index`th key of `width` bytes into `key`. Returns false if
+    /// there is no such key.
+    fn fill(&mut self, index: u64, width: usize, key: &mut Vec<u8>) -> bool {
+        key.resize(width, 0);
+        match self {
+            Nonces::Random(rng) => rng.fill(&mut key[..]),
+            Nonces::Seeded(rng) => {
+                let words_per_key = width.div_ceil(4) as u128;
+                rng.set_word_pos(u128::from(index) * words_per_key);
+                rng.fill(&mut key[..]);
+            }
+            Nonces::Sequential(start) => {
+                let bytes = start.wrapping_add(index).to_le_bytes();
+                let len = width.min(bytes.len());
+                key.iter_mut().for_each(|byte| *byte = 0);
+                key[..len].copy_from_slice(&bytes[..len]);
+            }
+            Nonces::List(keys) => match usize::try_from(index).ok().and_then(|i| keys.get(i)) {
+                Some(listed) => {
+                    key.clear();
+                    key.extend_from_slice(listed);
+                }
+                None => return false,
+            },
+        }
+        true
+    }
+}
+
+/// When to give up on mining.
+#[derive(Clone, Debug, Default)]
+struct Limits {
+    cancellation: CancellationToken,
+    timeout: Option<Duration>,
+    max_attempts: Option<u64>,
+}
+
+/// Does the proof-of-work for new messages.
+///
+/// The search for a key is split across several worker threads, which all
+/// stop as soon as any one of them finds a key that works. By default there
+/// is one worker for each core on the machine.
+///
+/// Mining can be given a budget of time or attempts, and can be cancelled
+/// with a [`CancellationToken`]. If it stops early, no message is produced.
+///
+/// Keys are random by default, but a [`NonceSource`] can be given to make
+/// mining reproducible.
+pub struct Miner<'h> {
+    threads: usize,
+    nonces: NonceSource,
+    iter: Box<dyn Fn(HexString) + Send + Sync + 'h>,
+    progress: Box<dyn Fn(&MiningStats) + Send + Sync + 'h>,
+    iter_period: u32,
+    limits: Limits,
+}
+
+impl<'h> Miner<'h> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many worker threads to search with. Anything less than 1 is
+    /// treated as 1.
+    pub fn with_threads(&mut self, threads: usize) -> &mut Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets where keys to try come from.
+    pub fn with_nonce_source(&mut self, nonces: NonceSource) -> &mut Self {
+        self.nonces = nonces;
+        self
+    }
+
+    /// Sets a hook that is called with the hash of every `period`th attempt,
+    /// counted across all workers.
+    ///
+    /// The hook may be called from any of the worker threads.
+    pub fn on_iter<F>(&mut self, iter_hook: F) -> &mut Self
+    where
+        F: Fn(HexString) + Send + Sync + 'h,
+    {
+        self.iter = Box::new(iter_hook);
+        self
+    }
+
+    /// Sets a hook that is called with how mining is going every `period`th
+    /// attempt, along with the `on_iter` hook.
+    ///
+    /// The hook may be called from any of the worker threads.
+    pub fn on_progress<F>(&mut self, progress_hook: F) -> &mut Self
+    where
+        F: Fn(&MiningStats) + Send + Sync + 'h,
+    {
+        self.progress = Box::new(progress_hook);
+        self
+    }
+
+    /// How many attempts go by between calls to the `on_iter` and
+    /// `on_progress` hooks. A period of 0 means the hooks are never called.
+    pub fn with_period(&mut self, period: u32) -> &mut Self {
+        self.iter_period = period;
+        self
+    }
+
+    /// Stops mining once `token` is cancelled.
+    pub fn with_cancellation(&mut self, token: CancellationToken) -> &mut Self {
+        self.limits.cancellation = token;
+        self
+    }
+
+    /// Stops mining once `timeout` has passed since it started.
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.limits.timeout = Some(timeout);
+        self
+    }
+
+    /// Stops mining after `max_attempts` attempts across all workers.
+    pub fn with_max_attempts(&mut self, max_attempts: u64) -> &mut Self {
+        self.limits.max_attempts = Some(max_attempts);
+        self
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Generate a message for `action` that can follow `previous`, linked
+    /// with the default hash algorithm.
+    ///
+    /// Returns [`Error::MiningStopped`] if mining was cancelled or ran out of
+    /// budget before a key was found.
+    pub fn mine<A: Action>(
+        &self,
+        previous: &Message<A>,
+        action: A,
+        ledger: &Ledger<A>,
+        state: &A::State,
+    ) -> Result<Message<A>, Error<A>> {
+        self.mine_with(&HashAlgorithm::default(), previous, action, ledger, state)
+    }
+
+    /// Generate a message for `action` that can follow `previous`, linked
+    /// with `hasher`.
+    ///
+    /// `ledger` holds the messages up to and including `previous`, and is
+    /// used with `state` to work out the [bit cost](Action::bit_cost).
+    pub fn mine_with<A: Action>(
+        &self,
+        hasher: &dyn LinkHasher,
+        previous: &Message<A>,
+        action: A,
+        ledger: &Ledger<A>,
+        state: &A::State,
+    ) -> Result<Message<A>, Error<A>> {
+        let digest = previous.get_hasher_for_payload(hasher, &action);
+        let threshold = action.bit_cost(ledger, state);
+        let prev_hash_bytes = stretch_key(&previous.key().to_bytes(), threshold);
+        let width = key_width(threshold);
+        let limits = &self.limits;
+        let start = Instant::now();
+        let deadline = limits.timeout.map(|timeout| start + timeout);
+        let attempts = AtomicU64::new(0);
+        let done = AtomicBool::new(false);
+        // The attempt number of the best key found so far. Workers keep going
+        // until they're past it, in case there is an earlier key that works.
+        let first_found = AtomicU64::new(u64::MAX);
+        let found: Mutex<Option<(u64, Vec<u8>)>> = Mutex::new(None);
+        let stopped: Mutex<Option<StopReason>> = Mutex::new(None);
+        let stop = |reason| {
+            stopped.lock().unwrap().get_or_insert(reason);
+            done.store(true, Ordering::Relaxed);
+        };
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    let mut nonces = Nonces::new(&self.nonces);
+                    let mut key = Vec::with_capacity(width);
+                    let mut worker_attempts = 0u64;
+                    while !done.load(Ordering::Relaxed) {
+                        if limits.cancellation.is_cancelled() {
+                            stop(StopReason::Cancelled);
+                            break;
+                        }
+                        if deadline.is_some()
+                            && worker_attempts.is_multiple_of(DEADLINE_CHECK_PERIOD)
+                            && deadline <= Some(Instant::now())
+                        {
+                            stop(StopReason::DeadlinePassed);
+                            break;
+                        }
+                        let attempt = attempts.fetch_add(1, Ordering::Relaxed);
+                        if attempt >= first_found.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if limits.max_attempts.is_some_and(|max| attempt >= max) {
+                            stop(StopReason::AttemptLimitReached);
+                            break;
+                        }
+                        if !nonces.fill(attempt, width, &mut key) {
+                            stop(StopReason::NoncesExhausted);
+                            break;
+                        }
+                        worker_attempts += 1;
+                        let mut key_hasher = digest.box_clone();
+                        key_hasher.update(&key);
+                        let hash = key_hasher.finalize();
+                        if self.iter_period != 0
+                            && (attempt + 1).is_multiple_of(u64::from(self.iter_period))
+                        {
+                            (self.iter)(HexString::from(&hash[..]));
+                            (self.progress)(&MiningStats::new(
+                                attempt + 1,
+                                start.elapsed(),
+                                threshold,
+                            ));
+                        }
+                        if bit_match(threshold, &prev_hash_bytes, &hash) {
+                            let mut found = found.lock().unwrap();
+                            if found.as_ref().is_none_or(|(best, _)| attempt < *best) {
+                                *found = Some((attempt, key.clone()));
+                            }
+                            first_found.fetch_min(attempt, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        match found.into_inner().unwrap() {
+            Some((_, key)) => Ok(Message::new(HexString::from(&key[..]), action)),
+            None => Err(Error::MiningStopped {
+                reason: stopped
+                    .into_inner()
+                    .unwrap()
+                    .expect("Workers only stop without a key if they hit a limit"),
+                attempts: attempts
+                    .into_inner()
+                    .min(limits.max_attempts.unwrap_or(u64::MAX)),
+            }),
+        }
+    }
+}
+
+impl Miner<'_> {
+    /// Measures how many attempts a second this miner makes, by hashing
+    /// messages with `hasher` the way mining does for about `duration`.
+    ///
+    /// Combine it with a bit cost in an [`Estimate`](super::Estimate) to
+    /// see how long mining should take.
+    pub fn benchmark<A: Action>(&self, hasher: &dyn LinkHasher, duration: Duration) -> f64 {
+        let previous = Message::<A>::default();
+        let digest = previous.get_hasher_for_payload(hasher, &A::default());
+        let attempts = AtomicU64::new(0);
+        let start = Instant::now();
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    let mut nonces = Nonces::new(&NonceSource::Random);
+                    let mut key = Vec::new();
+                    let mut worker_attempts = 0u64;
+                    loop {
+                        if worker_attempts.is_multiple_of(DEADLINE_CHECK_PERIOD)
+                            && start.elapsed() >= duration
+                        {
+                            break;
+                        }
+                        nonces.fill(worker_attempts, key_width(0), &mut key);
+                        let mut key_hasher = digest.box_clone();
+                        key_hasher.update(&key);
+                        key_hasher.finalize();
+                        worker_attempts += 1;
+                    }
+                    attempts.fetch_add(worker_attempts, Ordering::Relaxed);
+                });
+            }
+        });
+        attempts.into_inner() as f64 / start.elapsed().as_secs_f64()
+    }
+}
+
+impl Default for Miner<'_> {
+    fn default() -> Self {
+        Miner {
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            nonces: NonceSource::default(),
+            iter: Box::new(|_| {}),
+            progress: Box::new(|_| {}),
+            iter_period: u32::MAX,
+            limits: Limits::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::super::mode_a::{spells::Spell, ActionA};
+    use super::super::super::HashAlgorithm;
+    use super::super::{Error, Ledger, Message};
+    use super::{CancellationToken, Miner, NonceSource, StopReason};
+    use std::convert::TryInto;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn mined_message_is_accepted_by_previous_message() {
+        let state = Default::default();
+        let previous = Message::<ActionA>::default();
+        let action = ActionA::CastSpell {
+            spell: Spell::FireBall,
+        };
+        let next = Miner::new()
+            .with_threads(4)
+            .mine(&previous, action, &Ledger::new(), &state)
+            .unwrap();
+        assert!(previous.accepts_next_message(&next, &Ledger::new(), &state));
+    }
+
+    #[test]
+    fn iter_hook_is_called_from_workers() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let hook_calls = calls.clone();
+        let state = Default::default();
+        Miner::new()
+            .with_threads(2)
+            .with_period(1)
+            .on_iter(move |_| {
+                hook_calls.fetch_add(1, Ordering::Relaxed);
+            })
+            .mine(
+                &Message::<ActionA>::default(),
+                ActionA::NoOp,
+                &Ledger::new(),
+                &state,
+            )
+            .unwrap();
+        assert!(calls.load(Ordering::Relaxed) >= 1);
+    }
+
+    #[test]
+    fn progress_hook_gets_stats() {
+        let bit_costs = Arc::new(AtomicU64::new(0));
+        let hook_bit_costs = bit_costs.clone();
+        let state = Default::default();
+        Miner::new()
+            .with_period(1)
+            .on_progress(move |stats| {
+                assert!(stats.attempts() >= 1);
+                hook_bit_costs.store(stats.bit_cost() as u64, Ordering::Relaxed);
+            })
+            .mine(
+                &Message::<ActionA>::default(),
+                ActionA::CastSpell {
+                    spell: Spell::FireBall,
+                },
+                &Ledger::new(),
+                &state,
+            )
+            .unwrap();
+        assert_eq!(bit_costs.load(Ordering::Relaxed), 8);
+    }
+
+    #[test]
+    fn progress_hook_waits_for_a_full_period() {
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let hook_attempts = attempts.clone();
+        let state = Default::default();
+        let _ = Miner::new()
+            .with_threads(1)
+            .with_period(3)
+            .with_max_attempts(9)
+            .on_progress(move |stats| hook_attempts.lock().unwrap().push(stats.attempts()))
+            .mine(
+                &Message::<ActionA>::default(),
+                ActionA::CastSpell {
+                    spell: Spell::FireBall,
+                },
+                &Ledger::new(),
+                &state,
+            );
+        assert!(attempts.lock().unwrap().iter().all(|n| n % 3 == 0));
+    }
+
+    #[test]
+    fn benchmark_measures_some_hashes() {
+        let rate = Miner::new()
+            .with_threads(2)
+            .benchmark::<ActionA>(&HashAlgorithm::default(), Duration::from_millis(20));
+        assert!(rate > 0.0);
+    }
+
+    #[test]
+    fn mined_message_with_other_hash_is_accepted() {
+        let state = Default::default();
+        let previous = Message::<ActionA>::default();
+        let next = Miner::new()
+            .mine_with(
+                &HashAlgorithm::Blake2b,
+                &previous,
+                ActionA::NoOp,
+                &Ledger::new(),
+                &state,
+            )
+            .unwrap();
+        assert!(previous.accepts_next_message_with(
+            &HashAlgorithm::Blake2b,
+            &next,
+            &Ledger::new(),
+            &state
+        ));
+    }
+
+    #[test]
+    fn seeded_mining_gives_the_same_key_with_any_thread_count() {
+        let state = Default::default();
+        let previous = Message::<ActionA>::default();
+        let keys: Vec<_> = [1, 2, 5]
+            .iter()
+            .map(|threads| {
+                Miner::new()
+                    .with_threads(*threads)
+                    .with_nonce_source(NonceSource::Seeded(8))
+                    .mine(&previous, ActionA::NoOp, &Ledger::new(), &state)
+                    .unwrap()
+                    .key()
+                    .clone()
+            })
+            .collect();
+        assert_eq!(keys[0], keys[1]);
+        assert_eq!(keys[0], keys[2]);
+    }
+
+    #[test]
+    fn sequential_mining_finds_first_working_key() {
+        let state = Default::default();
+        let previous = Message::<ActionA>::default();
+        let next = Miner::new()
+            .with_threads(3)
+            .with_nonce_source(NonceSource::Sequential(0))
+            .mine(&previous, ActionA::NoOp, &Ledger::new(), &state)
+            .unwrap();
+        let n = u32::from_le_bytes(next.key().to_bytes()[..].try_into().unwrap());
+        let earlier_keys: Vec<Vec<u8>> = (0..n).map(|i| i.to_le_bytes().to_vec()).collect();
+        let earlier = Miner::new()
+            .with_nonce_source(NonceSource::List(earlier_keys))
+            .mine(&previous, ActionA::NoOp, &Ledger::new(), &state);
+        assert!(matches!(
+            earlier,
+            Err(Error::MiningStopped {
+                reason: StopReason::NoncesExhausted,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn listed_keys_are_used_as_is() {
+        let state = Default::default();
+        let previous = Message::<ActionA>::default();
+        let mined = previous.gen_next_message(ActionA::NoOp, &Ledger::new(), &state);
+        let next = Miner::new()
+            .with_nonce_source(NonceSource::List(vec![vec![1, 2], mined.key().to_bytes()]))
+            .mine(&previous, ActionA::NoOp, &Ledger::new(), &state)
+            .unwrap();
+        assert_eq!(next.key(), mined.key());
+    }
+
+    #[test]
+    fn cancelled_mining_stops_with_error() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let state = Default::default();
+        let result = Miner::new().with_cancellation(token).mine(
+            &Message::<ActionA>::default(),
+            ActionA::NoOp,
+            &Ledger::new(),
+            &state,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::MiningStopped {
+                reason: StopReason::Cancelled,
+                attempts: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn mining_stops_at_attempt_limit() {
+        let state = Default::default();
+        let result = Miner::new().with_threads(3).with_max_attempts(0).mine(
+            &Message::<ActionA>::default(),
+            ActionA::NoOp,
+            &Ledger::new(),
+            &state,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::MiningStopped {
+                reason: StopReason::AttemptLimitReached,
+                attempts: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn mining_stops_at_deadline() {
+        let state = Default::default();
+        let result = Miner::new().with_timeout(Duration::from_millis(0)).mine(
+            &Message::<ActionA>::default(),
+            ActionA::NoOp,
+            &Ledger::new(),
+            &state,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::MiningStopped {
+                reason: StopReason::DeadlinePassed,
+                ..
+            })
+        ));
+    }
+}
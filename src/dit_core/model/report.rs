@@ -0,0 +1,275 @@
+use super::super::Action;
+use super::{Error, Message, SignatureCheck};
+
+/// The result of checking a single message against the message before it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinkReport {
+    index: usize,
+    line_number: usize,
+    bit_cost: usize,
+    link_holds: bool,
+    signature: SignatureCheck,
+}
+
+impl LinkReport {
+    pub(crate) fn new(index: usize, line_number: usize, bit_cost: usize, link_holds: bool) -> Self {
+        LinkReport {
+            index,
+            line_number,
+            bit_cost,
+            link_holds,
+            signature: SignatureCheck::Unsigned,
+        }
+    }
+
+    pub(crate) fn with_signature(mut self, signature: SignatureCheck) -> Self {
+        self.signature = signature;
+        self
+    }
+
+    /// Position of the message in the book, starting at 0.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Line of the file the message was read from, starting at 1.
+    pub fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// How many bits of the hash had to match for this message.
+    pub fn bit_cost(&self) -> usize {
+        self.bit_cost
+    }
+
+    /// Whether the previous message accepts this one as its successor.
+    pub fn link_holds(&self) -> bool {
+        self.link_holds
+    }
+
+    /// Whether the message was signed by the player whose book it is.
+    pub fn signature(&self) -> SignatureCheck {
+        self.signature
+    }
+}
+
+/// Everything [`validate_read`](super::super::validate_read) learned about a
+/// book while replaying it.
+///
+/// Validation stops at the first broken link, so if the book is invalid the
+/// last entry of [`links`](ValidationReport::links) is the broken one and
+/// [`state`](ValidationReport::state) is the state just before it.
+pub struct ValidationReport<A: Action> {
+    links: Vec<LinkReport>,
+    state: A::State,
+    broken_link: Option<(Message<A>, Message<A>)>,
+}
+
+impl<A: Action> ValidationReport<A> {
+    pub(crate) fn new() -> Self {
+        ValidationReport {
+            links: Vec::new(),
+            state: A::State::default(),
+            broken_link: None,
+        }
+    }
+
+    pub(crate) fn push_link(&mut self, link: LinkReport) {
+        self.links.push(link);
+    }
+
+    pub(crate) fn set_state(&mut self, state: A::State) {
+        self.state = state;
+    }
+
+    pub(crate) fn break_link(&mut self, last_message: Message<A>, failed_message: Message<A>) {
+        self.broken_link = Some((last_message, failed_message));
+    }
+
+    /// One entry per message checked, in file order.
+    pub fn links(&self) -> &[LinkReport] {
+        &self.links[..]
+    }
+
+    /// The state after replaying every message that validated.
+    pub fn state(&self) -> &A::State {
+        &self.state
+    }
+
+    pub fn into_state(self) -> A::State {
+        self.state
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.broken_link.is_none()
+    }
+
+    /// The first message that wasn't signed by the player whose book it is,
+    /// if there is one. See [`Identity`](super::Identity).
+    pub fn first_unsigned(&self) -> Option<&LinkReport> {
+        self.links
+            .iter()
+            .find(|link| link.signature != SignatureCheck::Valid)
+    }
+
+    /// The message before the broken link and the message that failed to link
+    /// to it, if there was a broken link.
+    pub fn broken_link(&self) -> Option<(&Message<A>, &Message<A>)> {
+        self.broken_link
+            .as_ref()
+            .map(|(last_message, failed_message)| (last_message, failed_message))
+    }
+}
+
+/// Which check a message failed during forensic validation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkFailure {
+    /// The message isn't properly linked to the message before it.
+    Hash,
+    /// The action couldn't be applied to the state at that point.
+    Applicable,
+    /// The action was applicable, but applying it returned an error.
+    Apply,
+    /// The message is signed, but not by the player whose book it is.
+    Signature,
+}
+
+/// A run of consecutive messages that all failed the same check.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailureSpan {
+    failure: LinkFailure,
+    first: LinkReport,
+    last: LinkReport,
+}
+
+impl FailureSpan {
+    pub fn failure(&self) -> LinkFailure {
+        self.failure
+    }
+
+    /// The first message in the span.
+    pub fn first(&self) -> &LinkReport {
+        &self.first
+    }
+
+    /// The last message in the span, which may be the same as the first.
+    pub fn last(&self) -> &LinkReport {
+        &self.last
+    }
+
+    /// How many messages are in the span.
+    pub fn message_count(&self) -> usize {
+        self.last.index - self.first.index + 1
+    }
+}
+
+/// Everything [`validate_forensic_read`](super::super::validate_forensic_read)
+/// learned about a book, including every failure rather than just the first.
+///
+/// Messages that fail to apply are skipped, so [`state`](ForensicReport::state)
+/// is the state from replaying only the messages that could be applied.
+pub struct ForensicReport<A: Action> {
+    links: Vec<LinkReport>,
+    spans: Vec<FailureSpan>,
+    state: A::State,
+}
+
+impl<A: Action> ForensicReport<A> {
+    pub(crate) fn new() -> Self {
+        ForensicReport {
+            links: Vec::new(),
+            spans: Vec::new(),
+            state: A::State::default(),
+        }
+    }
+
+    pub(crate) fn push_link(&mut self, link: LinkReport) {
+        self.links.push(link);
+    }
+
+    /// Records that the message in `link` failed `failure`, extending the
+    /// span for the message before it if that failed the same way.
+    pub(crate) fn push_failure(&mut self, link: &LinkReport, failure: LinkFailure) {
+        let extends = self
+            .spans
+            .iter_mut()
+            .rev()
+            .find(|span| span.failure == failure && span.last.index + 1 == link.index);
+        match extends {
+            Some(span) => span.last = link.clone(),
+            None => self.spans.push(FailureSpan {
+                failure,
+                first: link.clone(),
+                last: link.clone(),
+            }),
+        }
+    }
+
+    pub(crate) fn set_state(&mut self, state: A::State) {
+        self.state = state;
+    }
+
+    /// One entry per message checked, in file order.
+    pub fn links(&self) -> &[LinkReport] {
+        &self.links[..]
+    }
+
+    /// Every span of failures, in the order they start in the file.
+    pub fn spans(&self) -> &[FailureSpan] {
+        &self.spans[..]
+    }
+
+    /// The state after replaying every message that could be applied.
+    pub fn state(&self) -> &A::State {
+        &self.state
+    }
+
+    pub fn into_state(self) -> A::State {
+        self.state
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.spans.is_empty()
+    }
+}
+
+/// What happened to each action passed to [`Book::rebase`](super::Book::rebase).
+///
+/// Actions are identified by their position in the list passed in.
+#[derive(Debug)]
+pub struct RebaseReport<A: Action> {
+    rebased: Vec<usize>,
+    skipped: Vec<(usize, Error<A>)>,
+}
+
+impl<A: Action> RebaseReport<A> {
+    pub(crate) fn new() -> Self {
+        RebaseReport {
+            rebased: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    pub(crate) fn rebase(&mut self, index: usize) {
+        self.rebased.push(index);
+    }
+
+    pub(crate) fn skip(&mut self, index: usize, err: Error<A>) {
+        self.skipped.push((index, err));
+    }
+
+    /// The actions that were mined onto the book.
+    pub fn rebased(&self) -> &[usize] {
+        &self.rebased[..]
+    }
+
+    /// The actions that no longer apply, and so were left out, with the
+    /// [`Error::BadAction`] saying why.
+    pub fn skipped(&self) -> &[(usize, Error<A>)] {
+        &self.skipped[..]
+    }
+
+    pub fn into_skipped(self) -> Vec<(usize, Error<A>)> {
+        self.skipped
+    }
+}
@@ -1,6 +1,11 @@
-use super::{Action, Error, Ledger, Message};
+use super::model::check_signature;
+use super::{
+    Action, Book, Diagnostic, Error, ForensicReport, Header, HexString, Identity, Ledger,
+    LedgerBuf, LinkFailure, LinkReport, Message, Miner, Mode, SignatureCheck, State,
+    ValidationReport,
+};
 use serde_json;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, Write};
 
 /// Should be renamed to something like "apply_action_to_file",
@@ -12,7 +17,7 @@ use std::io::{self, BufRead, BufReader, Write};
 /// Takes a filename, and a clojure that generates an Action (Or Error). If clojure returns successful,
 /// We attempt to apply it to the state, and if THAT works, we save it to the file.
 #[deprecated(
-    since = "0",
+    since = "0.1.0",
     note = "We'll be using book instead, only keeping this around to scale refactor"
 )]
 pub fn with_game_state<A, F>(file_name: &str, action_apply: F) -> Result<(), Error<A>>
@@ -27,29 +32,175 @@ where
         .open(file_name)
         .map_err(io_error(file_name))?;
 
-    let (state, last_message) = BufReader::new(&file).lines().try_fold(
-        (A::State::default(), Message::default()),
-        |(state, _), line_result| {
-            let line = line_result.map_err(io_error(file_name))?;
-            let new_message =
-                serde_json::from_str::<Message<A>>(line.as_str()).map_err(Error::SerdeError)?;
-            new_message
+    let is_new = file.metadata().map_err(io_error(file_name))?.len() == 0;
+    let (header, root_hash, state, messages) = {
+        let (header, mut message_lines) =
+            read_lines::<A, _>(BufReader::new(&file)).map_err(in_file(file_name))?;
+        let header = if is_new {
+            header.with_new_root_hash()
+        } else {
+            header
+        };
+        let root_hash = header.initial_state::<A::State>().root_hash();
+        let mut messages: Vec<Message<A>> = Vec::new();
+        let state = message_lines.try_fold(header.initial_state(), |state, message_result| {
+            let (line_number, new_message) = message_result?;
+            let index = messages.len();
+            let ledger = Ledger::from(&messages[..]).with_root_hash(&root_hash);
+            let state = new_message
                 .action()
-                .apply(&Ledger::new().with_hash(new_message.key()), state)
-                .map(|state| (state, new_message))
-        },
-    )?;
+                .apply(&ledger.with_hash(new_message.key()), state)
+                .map_err(|err| err.at_message(index).on_line(line_number))?;
+            messages.push(new_message);
+            Ok(state)
+        });
+        (
+            header,
+            root_hash,
+            state.map_err(in_file(file_name))?,
+            messages,
+        )
+    };
+    let hash_algorithm = header.hash_algorithm().map_err(in_file(file_name))?;
+    let genesis = Message::genesis(root_hash.clone());
+    let last_message = messages.last().unwrap_or(&genesis);
+    if is_new {
+        header.write_to(&mut file).map_err(io_error(file_name))?;
+    }
 
     let next_action = action_apply(&state)?;
-    let next_message = last_message.gen_next_message(next_action, &state);
+    let next_message = Miner::new().mine_with(
+        &hash_algorithm,
+        last_message,
+        next_action,
+        &Ledger::from(&messages[..]).with_root_hash(&root_hash),
+        &state,
+    )?;
     let message_string: String = dit_result(serde_json::to_string(&next_message))?;
-    write!(file, "{}\n", message_string).map_err(io_error(file_name))?;
+    writeln!(file, "{}", message_string).map_err(io_error(file_name))?;
     Ok(())
 }
 
+/// Opens the book saved in `file_name`, which has to exist.
+pub fn read_book<A: Action>(file_name: &str) -> Result<Book<A>, Error<A>> {
+    open_book(file_name, Book::from_read)?.ok_or_else(|| not_found(file_name))
+}
+
+/// Opens the book saved in `file_name`, or returns an empty book if the file
+/// doesn't exist yet. The file will be created by [`write_book`].
+///
+/// Empty files are read as new books too, so they get a header with a root
+/// hash like any other new book.
+pub fn read_or_new_book<A: Action>(file_name: &str) -> Result<Book<A>, Error<A>> {
+    Ok(open_book(file_name, Book::from_read)?.unwrap_or_default())
+}
+
+/// Opens the book saved in `file_name` without checking the links between
+/// messages, for looking at books that might have been tampered with.
+///
+/// See [`Book::from_read_trusted`].
+pub fn read_book_trusted<A: Action>(file_name: &str) -> Result<Book<A>, Error<A>> {
+    open_book(file_name, Book::from_read_trusted)?.ok_or_else(|| not_found(file_name))
+}
+
+/// Reads the book in `file_name` with `from_read`, or `None` if there is no
+/// such file. An empty file is a new book, as in [`with_game_state`].
+fn open_book<A, F>(file_name: &str, from_read: F) -> Result<Option<Book<A>>, Error<A>>
+where
+    A: Action,
+    F: FnOnce(File) -> Result<Book<A>, Error<A>>,
+{
+    match File::open(file_name) {
+        Ok(file) if file.metadata().map_err(io_error(file_name))?.len() == 0 => {
+            Ok(Some(Book::new()))
+        }
+        Ok(file) => from_read(file).map(Some).map_err(in_file(file_name)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(Error::IoError(String::from(file_name), err)),
+    }
+}
+
+fn not_found<A: Action>(file_name: &str) -> Error<A> {
+    Error::IoError(
+        String::from(file_name),
+        io::Error::new(io::ErrorKind::NotFound, "there's no such file"),
+    )
+}
+
+/// Finds the mode of the book in `file_name`, see [`Header::read_mode`].
+pub fn read_mode(file_name: &str) -> Option<Mode> {
+    Header::read_mode(BufReader::new(File::open(file_name).ok()?))
+}
+
+/// Saves any changes to `book` to the end of `file_name`, creating the file
+/// if needed.
+///
+/// If saved messages were removed from the book, the whole file is rewritten
+/// instead. The book is written to a temporary file first and then moved over
+/// the original, so the original is left alone if anything goes wrong.
+pub fn write_book<A: Action>(file_name: &str, book: &mut Book<A>) -> Result<(), Error<A>> {
+    if book.needs_rewrite() {
+        return replace_book(file_name, book);
+    }
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(file_name)
+        .map_err(io_error(file_name))?;
+    book.write_changes(&mut file).map_err(io_error(file_name))
+}
+
+/// Saves `book` to a new file, without touching any file that already exists
+/// at `file_name`.
+pub fn create_book<A: Action>(file_name: &str, book: &mut Book<A>) -> Result<(), Error<A>> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(file_name)
+        .map_err(io_error(file_name))?;
+    book.write_all(&mut file).map_err(io_error(file_name))
+}
+
+/// Replaces the file at `file_name` with the whole of `book`, going through a
+/// temporary file so that the original is left alone if anything goes wrong.
+pub fn replace_book<A: Action>(file_name: &str, book: &mut Book<A>) -> Result<(), Error<A>> {
+    let temp_name = format!("{}.rewrite", file_name);
+    let mut temp_file = File::create(&temp_name).map_err(io_error(&temp_name))?;
+    book.write_all(&mut temp_file)
+        .and_then(|_| temp_file.sync_all())
+        .map_err(io_error(&temp_name))?;
+    fs::rename(&temp_name, file_name).map_err(io_error(file_name))
+}
+
+/// Saves a new [`Identity`] to `file_name`, for signing messages later.
+///
+/// The file holds the secret key, so on Unix only its owner can read it. An
+/// existing file is never overwritten, since the identity in it would be lost.
+pub fn create_identity<A: Action>(file_name: &str) -> Result<Identity, Error<A>> {
+    let identity = Identity::generate();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(file_name).map_err(io_error(file_name))?;
+    writeln!(file, "{}", identity.secret_key()).map_err(io_error(file_name))?;
+    Ok(identity)
+}
+
+/// Reads an [`Identity`] saved by [`create_identity`].
+pub fn read_identity<A: Action>(file_name: &str) -> Result<Identity, Error<A>> {
+    let contents = fs::read_to_string(file_name).map_err(io_error(file_name))?;
+    HexString::parse(contents.trim())
+        .and_then(|secret_key| Identity::from_secret_key(&secret_key))
+        .ok_or_else(|| {
+            let err = io::Error::new(io::ErrorKind::InvalidData, "this isn't a dit identity");
+            Error::IoError(String::from(file_name), err)
+        })
+}
+
 /// Read file to game state and ledger
 /// Later will be refactored to take in any Read
-pub fn read_state<A>(file_name: &str) -> Result<(A::State, Ledger<A>), Error<A>>
+pub fn read_state<A>(file_name: &str) -> Result<(A::State, LedgerBuf<A>), Error<A>>
 where
     A: Action,
 {
@@ -58,55 +209,231 @@ where
         .open(file_name)
         .map_err(io_error(file_name))?;
 
-    let file_lines: Vec<String> = BufReader::new(&file)
-        .lines()
-        .collect::<Result<Vec<String>, io::Error>>()
-        .map_err(io_error(file_name))?;
-    let message_vec: Vec<Message<A>> = file_lines
-        .iter()
-        .map(|line| serde_json::from_str::<Message<A>>(line.as_str()))
-        .collect::<Result<_, _>>()
-        .map_err(Error::SerdeError)?;
-    let ledger: Ledger<A> = Ledger::from(&message_vec[..]);
+    let (header, message_lines) =
+        read_lines::<A, _>(BufReader::new(&file)).map_err(in_file(file_name))?;
+    let hash_algorithm = header.hash_algorithm().map_err(in_file(file_name))?;
+    let (line_numbers, message_vec): (Vec<usize>, Vec<Message<A>>) = message_lines
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(in_file(file_name))?
+        .into_iter()
+        .unzip();
+    let initial_state: A::State = header.initial_state();
+    let root_hash = initial_state.root_hash();
     let state = message_vec
         .iter()
-        .try_fold(A::State::default(), |state, message| {
+        .zip(line_numbers)
+        .zip(0..)
+        .try_fold(initial_state, |state, ((message, line_number), index)| {
+            let ledger = Ledger::from(&message_vec[..index]).with_root_hash(&root_hash);
             message
                 .action()
                 .apply(&ledger.with_hash(message.key()), state)
-        })?;
-    Ok((state, Ledger::default())) // BROKEN
+                .map_err(|err| err.at_message(index).on_line(line_number))
+        })
+        .map_err(in_file(file_name))?;
+    Ok((
+        state,
+        LedgerBuf::from(message_vec)
+            .with_root_hash(root_hash)
+            .with_hash_algorithm(hash_algorithm),
+    ))
 }
 
 /// Checks whether a file is valid by checking the hashes of the Messages
 /// It also fully constructs the game state in the process, since we sometimes
 /// need that state to determine the number of bits that need to match.
-pub fn validate<A: Action>(file_name: &str) -> Result<(), Error<A>> {
+///
+/// A broken link is reported as [`Error::FailedValidation`]. Use
+/// [`validate_read`] directly to get the report for an invalid book instead.
+pub fn validate<A: Action>(file_name: &str) -> Result<ValidationReport<A>, Error<A>> {
     let file = File::open(file_name).map_err(io_error(file_name))?;
-    let lines = BufReader::new(file).lines();
-    lines
-        .map(|line_result| line_result.unwrap())
-        .map(|line| serde_json::from_str::<Message<A>>(line.as_str()).unwrap()) // Should probably refactor to return a SeDe error
-        .zip(1..)
-        .try_fold(
-            (A::State::default(), Message::default()),
-            |(state, last_message), (next_message, line_number)| {
-                if last_message.accepts_next_message(&next_message, &state) {
-                    next_message
-                        .action()
-                        .apply(&Ledger::new().with_hash(next_message.key()), state)
-                        .map(|state| (state, next_message))
-                } else {
-                    Err(Error::FailedValidation {
-                        file_name: String::from(file_name),
-                        failed_message: next_message,
-                        last_message,
-                        line_number,
-                    })
+    let report = validate_read(BufReader::new(file)).map_err(in_file(file_name))?;
+    match report.broken_link() {
+        Some((last_message, failed_message)) => Err(Error::FailedValidation {
+            file_name: String::from(file_name),
+            line_number: report.links().last().map_or(0, LinkReport::line_number),
+            last_message: Box::new(last_message.clone()),
+            failed_message: Box::new(failed_message.clone()),
+        }),
+        None => Ok(report),
+    }
+}
+
+/// Checks whether the messages read from `reader` form a valid book.
+///
+/// Unlike [`validate`], a broken link is not an error: the returned report
+/// lists every message checked up to and including the first broken link,
+/// along with the state built from the messages before it. Errors are only
+/// returned if the reader fails, a line can't be parsed, an action can't be
+/// applied, or a message is signed by someone other than the book's player.
+///
+/// Unsigned messages are fine here, but can be found with
+/// [`ValidationReport::first_unsigned`].
+pub fn validate_read<A, R>(reader: R) -> Result<ValidationReport<A>, Error<A>>
+where
+    A: Action,
+    R: BufRead,
+{
+    let (header, message_lines) = read_lines(reader)?;
+    let hasher = header.hash_algorithm()?;
+    let mut report = ValidationReport::new();
+    let mut messages: Vec<Message<A>> = Vec::new();
+    let mut state: A::State = header.initial_state();
+    let root_hash = state.root_hash();
+    let genesis = Message::genesis(root_hash.clone());
+    let public_key = header.public_key();
+    for (message_result, index) in message_lines.zip(0..) {
+        let (line_number, next_message) = message_result?;
+        let ledger = Ledger::from(&messages[..]).with_root_hash(&root_hash);
+        let bit_cost = next_message.action().bit_cost(&ledger, &state);
+        let last_message = messages.last().unwrap_or(&genesis);
+        let link_holds =
+            last_message.accepts_next_message_with(&hasher, &next_message, &ledger, &state);
+        let signature = check_signature(public_key.as_ref(), &next_message);
+        report.push_link(
+            LinkReport::new(index, line_number, bit_cost, link_holds).with_signature(signature),
+        );
+        if link_holds && signature == SignatureCheck::Invalid {
+            return Err(Error::BadSignature {
+                line_number: Some(line_number),
+                message: Box::new(next_message),
+            });
+        }
+        if !link_holds {
+            report.break_link(last_message.clone(), next_message);
+            break;
+        }
+        state = next_message
+            .action()
+            .apply(&ledger.with_hash(next_message.key()), state)
+            .map_err(|err| err.at_message(index).on_line(line_number))?;
+        messages.push(next_message);
+    }
+    report.set_state(state);
+    Ok(report)
+}
+
+/// Checks every message in a file, carrying on past any that fail.
+///
+/// See [`validate_forensic_read`].
+pub fn validate_forensic<A: Action>(file_name: &str) -> Result<ForensicReport<A>, Error<A>> {
+    let file = File::open(file_name).map_err(io_error(file_name))?;
+    validate_forensic_read(BufReader::new(file)).map_err(in_file(file_name))
+}
+
+/// Checks every message read from `reader`, for finding everywhere a book has
+/// been tampered with.
+///
+/// Unlike [`validate_read`], this keeps replaying past a broken link. Each
+/// message is checked against the message before it in the file, and its
+/// action is applied if it can be, even if its link is broken. Messages whose
+/// actions can't be applied are skipped. Consecutive failures of the same
+/// kind are grouped into spans in the report.
+pub fn validate_forensic_read<A, R>(reader: R) -> Result<ForensicReport<A>, Error<A>>
+where
+    A: Action,
+    R: BufRead,
+{
+    let (header, message_lines) = read_lines(reader)?;
+    let hasher = header.hash_algorithm()?;
+    let mut report = ForensicReport::new();
+    let mut applied: Vec<Message<A>> = Vec::new();
+    let mut state: A::State = header.initial_state();
+    let root_hash = state.root_hash();
+    let mut last_message = Message::genesis(root_hash.clone());
+    let public_key = header.public_key();
+    for (message_result, index) in message_lines.zip(0..) {
+        let (line_number, next_message) = message_result?;
+        let ledger = Ledger::from(&applied[..]).with_root_hash(&root_hash);
+        let bit_cost = next_message.action().bit_cost(&ledger, &state);
+        let link_holds =
+            last_message.accepts_next_message_with(&hasher, &next_message, &ledger, &state);
+        let signature = check_signature(public_key.as_ref(), &next_message);
+        let link =
+            LinkReport::new(index, line_number, bit_cost, link_holds).with_signature(signature);
+        if !link_holds {
+            report.push_failure(&link, LinkFailure::Hash);
+        }
+        if signature == SignatureCheck::Invalid {
+            report.push_failure(&link, LinkFailure::Signature);
+        }
+        if next_message.action().applicable(&ledger, &state).is_err() {
+            report.push_failure(&link, LinkFailure::Applicable);
+        } else {
+            match next_message
+                .action()
+                .apply(&ledger.with_hash(next_message.key()), state)
+            {
+                Ok(next_state) => {
+                    state = next_state;
+                    applied.push(next_message.clone());
                 }
-            },
-        )
-        .and(Ok(())) // We don't want to pass final value of the fold on
+                Err(_) => {
+                    report.push_failure(&link, LinkFailure::Apply);
+                    // The failed apply used up the state, so rebuild it
+                    state = replay(&header, &applied)?;
+                }
+            }
+        }
+        report.push_link(link);
+        last_message = next_message;
+    }
+    report.set_state(state);
+    Ok(report)
+}
+
+/// Rebuilds the state from applying `messages` in order.
+fn replay<A: Action>(header: &Header, messages: &[Message<A>]) -> Result<A::State, Error<A>> {
+    let initial_state: A::State = header.initial_state();
+    let root_hash = initial_state.root_hash();
+    messages
+        .iter()
+        .zip(0..)
+        .try_fold(initial_state, |state, (message, index)| {
+            message.action().apply(
+                &Ledger::from(&messages[..index])
+                    .with_root_hash(&root_hash)
+                    .with_hash(message.key()),
+                state,
+            )
+        })
+}
+
+/// A message read from a file, along with the line number it was read from.
+type MessageLine<A> = Result<(usize, Message<A>), Error<A>>;
+
+/// Reads the header of a book, and returns it with an iterator over the
+/// messages that follow and the line numbers they are on.
+///
+/// Files without a header are treated as having an empty header for the mode
+/// of `A`. Files with a header for a different mode result in
+/// [`Error::WrongMode`].
+pub(crate) fn read_lines<A, R>(
+    reader: R,
+) -> Result<(Header, impl Iterator<Item = MessageLine<A>>), Error<A>>
+where
+    A: Action,
+    R: BufRead,
+{
+    let mut lines = reader.lines().peekable();
+    let (header, first_line_number) = match Header::read_from(&mut lines)? {
+        Some(header) => {
+            header.check_mode()?;
+            let first_line_number = header.line_count() + 1;
+            (header, first_line_number)
+        }
+        None => (Header::new(A::State::mode()), 1),
+    };
+    let message_lines = lines
+        .zip(first_line_number..)
+        .map(|(line_result, line_number)| {
+            let line = line_result.map_err(Error::IoError2)?;
+            let message = serde_json::from_str::<Message<A>>(line.as_str()).map_err(|err| {
+                Error::InvalidLine(Diagnostic::for_message_line::<A>(line_number, &line, err))
+            })?;
+            Ok((line_number, message))
+        });
+    Ok((header, message_lines))
 }
 
 /// Allows use of .map_err(io_error(file_name)) when an error occurs to make a
@@ -116,6 +443,39 @@ pub fn io_error<A: Action>(file_name: &str) -> impl FnOnce(io::Error) -> Error<A
     move |error| Error::IoError(file_name_owned, error)
 }
 
+/// Fills in `file_name` for errors from reading a book that don't know which
+/// file it came from.
+fn in_file<A: Action>(file_name: &str) -> impl Fn(Error<A>) -> Error<A> + '_ {
+    move |err| match err {
+        Error::IoError2(err) => Error::IoError(String::from(file_name), err),
+        Error::InvalidLine(diagnostic) => Error::InvalidLine(diagnostic.with_file_name(file_name)),
+        Error::BadAction {
+            action,
+            index,
+            file_name: None,
+            line_number,
+            reason,
+        } => Error::BadAction {
+            action,
+            index,
+            file_name: Some(String::from(file_name)),
+            line_number,
+            reason,
+        },
+        Error::BrokenLink {
+            line_number,
+            last_message,
+            failed_message,
+        } => Error::FailedValidation {
+            file_name: String::from(file_name),
+            line_number: line_number.unwrap_or(0),
+            last_message,
+            failed_message,
+        },
+        err => err,
+    }
+}
+
 /// TODO Make this use a trait to be a bit more generic
 pub fn dit_result<A: Action, T>(result: Result<T, serde_json::Error>) -> Result<T, Error<A>> {
     result.map_err(Error::SerdeError)
@@ -123,24 +483,347 @@ pub fn dit_result<A: Action, T>(result: Result<T, serde_json::Error>) -> Result<
 
 #[cfg(test)]
 mod test {
+    use super::super::super::mode_a::{ActionA, StateA};
+    use super::super::{
+        Error, HashAlgorithm, Header, HexString, Identity, Ledger, Message, Miner, Mode,
+        NonceSource,
+    };
+    #[allow(deprecated)]
+    use super::{
+        read_book, read_or_new_book, read_state, validate_forensic_read, validate_read,
+        with_game_state, write_book, Book, LinkFailure, LinkReport,
+    };
+    use std::io::Cursor;
+
+    fn marker(content: &str) -> ActionA {
+        ActionA::Marker {
+            content: String::from(content),
+        }
+    }
 
     #[test]
-    #[ignore]
     fn test_validate() {
-        unimplemented!("Currently not sure how to do this as validate takes a filename as input. Will probably need to refactor")
+        let state = <ActionA as super::Action>::State::default();
+        let first = Message::default().gen_next_message(marker("first"), &Ledger::new(), &state);
+        let ledger = [first.clone()];
+        let second = first.gen_next_message(marker("second"), &Ledger::from(&ledger[..]), &state);
+        let file = format!("{}\n{}\n", first, second);
+
+        let report = validate_read::<ActionA, _>(Cursor::new(file)).expect("Should be readable");
+        assert!(report.is_valid());
+        assert_eq!(report.links().len(), 2);
+        assert!(report.links().iter().all(|link| link.link_holds()));
+        assert_eq!(report.links()[1].index(), 1);
+        assert_eq!(report.links()[1].line_number(), 2);
+        assert_eq!(report.links()[1].bit_cost(), 5);
+        assert_eq!(report.state().version(), state.version());
+    }
+
+    #[test]
+    fn validate_read_stops_at_broken_link() {
+        let tampered = include_str!("../../examples/.tampered");
+        let report =
+            validate_read::<ActionA, _>(Cursor::new(tampered)).expect("Should be readable");
+        assert!(!report.is_valid());
+        assert_eq!(report.links().len(), 2);
+        assert!(report.links()[0].link_holds());
+        assert!(!report.links()[1].link_holds());
+        let (last_message, failed_message) = report.broken_link().unwrap();
+        assert_eq!(last_message.key().to_string(), "b5fb3141");
+        assert_eq!(failed_message.key().to_string(), "208968bd");
+    }
+
+    #[test]
+    fn validate_read_counts_lines_after_header() {
+        let file = format!("\"A\"\n\"---\"\n{}", include_str!("../../examples/.dit"));
+        let report = validate_read::<ActionA, _>(Cursor::new(file)).expect("Should be readable");
+        assert!(report.is_valid());
+        assert_eq!(report.links()[0].line_number(), 3);
+    }
+
+    #[test]
+    fn validate_read_finds_unsigned_messages() {
+        let identity = Identity::generate();
+        let mut book = Book::<ActionA>::new_signed(&identity);
+        book.append_signed(marker("signed"), &Miner::new(), &identity)
+            .unwrap();
+        let mut written = Vec::new();
+        book.write_all(&mut written).unwrap();
+        let report = validate_read::<ActionA, _>(Cursor::new(&written)).unwrap();
+        assert_eq!(report.first_unsigned(), None);
+
+        book.append(marker("unsigned"), &Miner::new()).unwrap();
+        book.write_changes(&mut written).unwrap();
+        let report = validate_read::<ActionA, _>(Cursor::new(&written)).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.first_unsigned().map(LinkReport::index), Some(1));
+
+        let legacy = include_str!("../../examples/.dit");
+        let report = validate_read::<ActionA, _>(Cursor::new(legacy)).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.first_unsigned().map(LinkReport::index), Some(0));
+    }
+
+    #[test]
+    fn validate_read_uses_hash_algorithm_from_header() {
+        let state = <ActionA as super::Action>::State::default();
+        let first = Miner::new()
+            .mine_with(
+                &HashAlgorithm::Sha3_256,
+                &Message::default(),
+                marker("first"),
+                &Ledger::new(),
+                &state,
+            )
+            .unwrap();
+        let file = format!("\"A\"\n\"hash=sha3-256\"\n\"---\"\n{}\n", first);
+        let report = validate_read::<ActionA, _>(Cursor::new(file)).expect("Should be readable");
+        assert!(report.is_valid());
+
+        let file = format!("\"A\"\n\"hash=md5\"\n\"---\"\n{}\n", first);
+        assert!(matches!(
+            validate_read::<ActionA, _>(Cursor::new(file)),
+            Err(Error::UnknownHashAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn validate_read_says_which_message_cant_be_applied() {
+        let mut book = Book::<ActionA>::new();
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        let downgrade = book.last_message().gen_next_message(
+            ActionA::UpdateVersion { version: 150 },
+            &book.ledger(),
+            book.state(),
+        );
+        let mut written = Vec::new();
+        book.write_all(&mut written).unwrap();
+        let file = format!("{}{}\n", String::from_utf8(written).unwrap(), downgrade);
+        let line_count = file.lines().count();
+
+        match validate_read::<ActionA, _>(Cursor::new(file)) {
+            Err(Error::BadAction {
+                index, line_number, ..
+            }) => {
+                assert_eq!(index, Some(1));
+                assert_eq!(line_number, Some(line_count));
+            }
+            _ => panic!("expected a bad action"),
+        }
     }
 
     #[test]
-    #[ignore]
+    fn validate_read_returns_error_for_unparsable_line() {
+        let result = validate_read::<ActionA, _>(Cursor::new("Bag of beans\n"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn write_book_appends_to_file_read_by_read_book() {
+        let file_name = std::env::temp_dir().join(format!("dit-test-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let _ = std::fs::remove_file(file_name);
+
+        assert!(read_book::<ActionA>(file_name).is_err());
+        let mut book = read_or_new_book::<ActionA>(file_name).unwrap();
+        book.append(marker("first"), &Miner::new()).unwrap();
+        write_book(file_name, &mut book).unwrap();
+
+        let mut book = read_book::<ActionA>(file_name).unwrap();
+        assert_eq!(book.ledger().messages().len(), 1);
+        book.append(ActionA::UpdateVersion { version: 200 }, &Miner::new())
+            .unwrap();
+        write_book(file_name, &mut book).unwrap();
+
+        let book = read_book::<ActionA>(file_name).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        assert_eq!(book.ledger().messages().len(), 2);
+        assert_eq!(book.state().version(), 200);
+    }
+
+    #[test]
+    fn read_state_uses_header_from_file() {
+        let file_name = std::env::temp_dir().join(format!("dit-header-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let header = Header::new(Mode::A).with_hash_algorithm(HashAlgorithm::Blake2b);
+        let mut book = Book::<ActionA>::with_header(header).unwrap();
+        book.append(marker("first"), &Miner::new()).unwrap();
+        let _ = std::fs::remove_file(file_name);
+        write_book(file_name, &mut book).unwrap();
+        let (_, ledger) = read_state::<ActionA>(file_name).unwrap();
+        assert_eq!(ledger.hash_algorithm(), HashAlgorithm::Blake2b);
+
+        std::fs::write(file_name, "\"A\"\n\"hash=md5\"\n\"---\"\n").unwrap();
+        let unknown = read_state::<ActionA>(file_name);
+        std::fs::write(file_name, "\"A\"\n\"hash=sha3-256\"\n").unwrap();
+        let unterminated = read_state::<ActionA>(file_name);
+        std::fs::write(file_name, "\"A\"\n\"---\"\nBag of beans\n").unwrap();
+        let invalid = read_state::<ActionA>(file_name);
+        std::fs::remove_file(file_name).unwrap();
+        assert!(matches!(unknown, Err(Error::UnknownHashAlgorithm(_))));
+        assert!(matches!(unterminated, Err(Error::UnterminatedHeader)));
+        match invalid {
+            Err(Error::InvalidLine(diagnostic)) => {
+                assert_eq!(diagnostic.file_name(), Some(file_name))
+            }
+            _ => panic!("expected an invalid line"),
+        }
+    }
+
+    #[test]
+    fn empty_file_is_read_as_new_book() {
+        let file_name = std::env::temp_dir().join(format!("dit-empty-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        std::fs::write(file_name, "").unwrap();
+        let mut book = read_or_new_book::<ActionA>(file_name).unwrap();
+        book.append(marker("first"), &Miner::new()).unwrap();
+        write_book(file_name, &mut book).unwrap();
+
+        let contents = std::fs::read_to_string(file_name).unwrap();
+        let read_back = read_book::<ActionA>(file_name);
+        std::fs::remove_file(file_name).unwrap();
+        assert!(contents.starts_with("\"A\"\n"));
+        let read_back = read_back.unwrap();
+        assert!(read_back.header().root_hash().is_some());
+        assert_eq!(read_back.header().root_hash(), book.header().root_hash());
+        assert_eq!(read_back.messages().len(), 1);
+    }
+
+    #[test]
+    fn read_state_returns_messages_from_file() {
+        let file_name = std::env::temp_dir().join(format!("dit-state-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let mut book = Book::<ActionA>::new();
+        book.append(marker("first"), &Miner::new()).unwrap();
+        book.append(marker("second"), &Miner::new()).unwrap();
+        let _ = std::fs::remove_file(file_name);
+        write_book(file_name, &mut book).unwrap();
+
+        let (state, ledger) = read_state::<ActionA>(file_name).unwrap();
+        std::fs::remove_file(file_name).unwrap();
+        assert_eq!(ledger.hash_algorithm(), HashAlgorithm::default());
+        assert_eq!(state.version(), book.state().version());
+        assert_eq!(ledger.messages().len(), 2);
+        assert_eq!(ledger.last_message().key(), book.last_message().key());
+        let next =
+            ledger
+                .last_message()
+                .gen_next_message(marker("third"), &ledger.ledger(), &state);
+        assert!(book
+            .last_message()
+            .accepts_next_message(&next, &book.ledger(), &state));
+    }
+
+    #[test]
+    fn validate_forensic_read_reports_every_failure() {
+        let mut miner = Miner::new();
+        miner.with_nonce_source(NonceSource::Sequential(0));
+        let root_hash = HexString::from(&[1, 2, 3, 4][..]);
+        let header = Header::new(Mode::A).with_root_hash(&root_hash);
+        let mut book = Book::<ActionA>::with_header(header).unwrap();
+        for content in ["zero", "one", "two"].iter() {
+            book.append(marker(content), &miner).unwrap();
+        }
+        book.append(ActionA::UpdateVersion { version: 200 }, &miner)
+            .unwrap();
+        book.append(marker("four"), &miner).unwrap();
+        let mut written = Vec::new();
+        book.write_changes(&mut written).unwrap();
+        let tampered = String::from_utf8(written)
+            .unwrap()
+            .replace("one", "uno")
+            .replace("two", "dos")
+            .replace("\"version\":200", "\"version\":50");
+
+        let report = validate_forensic_read::<ActionA, _>(Cursor::new(tampered)).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.links().len(), 5);
+        let spans: Vec<(LinkFailure, usize, usize)> = report
+            .spans()
+            .iter()
+            .map(|span| (span.failure(), span.first().index(), span.last().index()))
+            .collect();
+        assert_eq!(
+            spans,
+            [(LinkFailure::Hash, 1, 3), (LinkFailure::Applicable, 3, 3)]
+        );
+        assert_eq!(report.spans()[0].first().line_number(), 5);
+        assert_eq!(report.state().version(), 1_00);
+    }
+
+    #[test]
+    fn validate_forensic_read_accepts_valid_file() {
+        let valid = include_str!("../../examples/.dit");
+        let report = validate_forensic_read::<ActionA, _>(Cursor::new(valid)).unwrap();
+        assert!(report.is_valid());
+        assert_eq!(report.links().len(), 2);
+    }
+
+    #[test]
+    fn write_book_rewrites_truncated_book() {
+        let file_name = std::env::temp_dir().join(format!("dit-undo-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let _ = std::fs::remove_file(file_name);
+        let mut book = Book::<ActionA>::new();
+        book.append(marker("keep"), &Miner::new()).unwrap();
+        book.append(marker("drop"), &Miner::new()).unwrap();
+        write_book(file_name, &mut book).unwrap();
+
+        let mut book = read_book::<ActionA>(file_name).unwrap();
+        book.truncate_to(1).unwrap();
+        write_book(file_name, &mut book).unwrap();
+        book.append(marker("again"), &Miner::new()).unwrap();
+        write_book(file_name, &mut book).unwrap();
+
+        let contents = std::fs::read_to_string(file_name).unwrap();
+        let read_back = read_book::<ActionA>(file_name);
+        std::fs::remove_file(file_name).unwrap();
+        assert!(contents.contains("keep"));
+        assert!(!contents.contains("drop"));
+        assert!(contents.contains("again"));
+        assert_eq!(read_back.unwrap().ledger().messages().len(), 2);
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_with_game_state_clojure_ends_with_result_of_action() {
-        unimplemented!("Currently not sure how to do this as game state takes a filename as input. Will probably need to refactor.")
+        let file_name = std::env::temp_dir().join(format!("dit-game-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let _ = std::fs::remove_file(file_name);
+        let marker = || ActionA::Marker {
+            content: String::from("bagel bites"),
+        };
+        with_game_state(file_name, |_| Ok(marker())).unwrap();
+
+        let book = read_book::<ActionA>(file_name);
+        std::fs::remove_file(file_name).unwrap();
+        let book = book.unwrap();
+        assert_eq!(book.messages().len(), 1);
+        assert_eq!(
+            book.messages()[0].action().to_string(),
+            marker().to_string()
+        );
     }
 
     #[test]
-    #[ignore]
+    #[allow(deprecated)]
     fn test_with_game_state_clojure_ends_with_result_of_action_and_update() {
-        unimplemented!("Currently not sure how to do this as game state takes a filename as input. Will probably need to refactor.")
-        // Also might need to change this implementation: The Update is beter as an action onto state output than the clojure that produces an action.
-        // It might also be better implemented with an Option in both cases
+        let file_name = std::env::temp_dir().join(format!("dit-update-{}", std::process::id()));
+        let file_name = file_name.to_str().unwrap();
+        let _ = std::fs::remove_file(file_name);
+        let update = |state: &StateA| {
+            Ok(ActionA::UpdateVersion {
+                version: state.version() + 100,
+            })
+        };
+        with_game_state(file_name, update).unwrap();
+        with_game_state(file_name, update).unwrap();
+
+        let book = read_book::<ActionA>(file_name);
+        std::fs::remove_file(file_name).unwrap();
+        let book = book.unwrap();
+        assert_eq!(book.messages().len(), 2);
+        assert_eq!(book.state().version(), 300);
     }
 }
@@ -0,0 +1,109 @@
+//! Finding the [`Action`] that implements a [`Mode`], so that a book can be
+//! handled without knowing its mode ahead of time.
+
+use super::{Action, Mode, State};
+
+/// Something to do with a book whose mode is only known at runtime.
+///
+/// [`ModeRegistry::dispatch`] calls `visit` with the [`Action`] registered
+/// for the mode.
+pub trait ModeVisitor {
+    type Output;
+
+    fn visit<A: Action>(self) -> Self::Output;
+}
+
+/// Calls [`ModeVisitor::visit`] for one particular action.
+type VisitFn<V> = fn(V) -> <V as ModeVisitor>::Output;
+
+/// Maps each mode to the [`Action`] that implements it.
+///
+/// Since Rust can't store a type to use later, the registry stores a
+/// `visit::<A>` function for each mode instead, which is why it is specific
+/// to one kind of [`ModeVisitor`].
+///
+/// ```ignore
+/// let mut registry = ModeRegistry::new();
+/// registry.register::<ActionA>().register::<MyModeAction>();
+/// registry.dispatch(mode, PrintState { file_name });
+/// ```
+pub struct ModeRegistry<V: ModeVisitor> {
+    modes: Vec<(Mode, VisitFn<V>)>,
+}
+
+impl<V: ModeVisitor> ModeRegistry<V> {
+    pub fn new() -> Self {
+        ModeRegistry { modes: Vec::new() }
+    }
+
+    /// Registers `A` for the mode its state is for, replacing any action
+    /// already registered for that mode.
+    pub fn register<A: Action>(&mut self) -> &mut Self {
+        let mode = A::State::mode();
+        self.modes.retain(|(registered, _)| *registered != mode);
+        self.modes.push((mode, visit::<A, V>));
+        self
+    }
+
+    /// Every registered mode, in the order they were registered.
+    pub fn modes(&self) -> Vec<Mode> {
+        self.modes.iter().map(|(mode, _)| *mode).collect()
+    }
+
+    pub fn contains(&self, mode: Mode) -> bool {
+        self.modes.iter().any(|(registered, _)| *registered == mode)
+    }
+
+    /// Calls `visitor` with the action registered for `mode`, or returns
+    /// `None` if nothing is registered for it.
+    pub fn dispatch(&self, mode: Mode, visitor: V) -> Option<V::Output> {
+        self.modes
+            .iter()
+            .find(|(registered, _)| *registered == mode)
+            .map(|(_, visit)| visit(visitor))
+    }
+}
+
+impl<V: ModeVisitor> Default for ModeRegistry<V> {
+    fn default() -> Self {
+        ModeRegistry::new()
+    }
+}
+
+fn visit<A: Action, V: ModeVisitor>(visitor: V) -> V::Output {
+    visitor.visit::<A>()
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::mode_a::ActionA;
+    use super::super::{Action, Mode, State};
+    use super::{ModeRegistry, ModeVisitor};
+
+    struct ModeOf;
+
+    impl ModeVisitor for ModeOf {
+        type Output = Mode;
+
+        fn visit<A: Action>(self) -> Mode {
+            A::State::mode()
+        }
+    }
+
+    #[test]
+    fn dispatch_finds_registered_mode() {
+        let mut registry = ModeRegistry::new();
+        registry.register::<ActionA>();
+        assert_eq!(registry.dispatch(Mode::A, ModeOf), Some(Mode::A));
+        assert_eq!(registry.dispatch(Mode::new('λ'), ModeOf), None);
+        assert!(registry.contains(Mode::A));
+        assert_eq!(registry.modes(), [Mode::A]);
+    }
+
+    #[test]
+    fn register_replaces_mode() {
+        let mut registry = ModeRegistry::<ModeOf>::new();
+        registry.register::<ActionA>().register::<ActionA>();
+        assert_eq!(registry.modes(), [Mode::A]);
+    }
+}
@@ -1,5 +1,6 @@
 use super::{Error, HexString, Ledger, Mode, PendingLedger};
 use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
 
 /// Represents a change in state.
 ///
@@ -16,19 +17,31 @@ use serde::{de::DeserializeOwned, Serialize};
 pub trait Action: ToString + Serialize + DeserializeOwned + Default + Clone {
     type State: State;
     // fn apply(&self, state: Self::State) -> Self::State; //Option<Self::State> or Result<<Self::State, Error> ?
+    /// Any randomness should come from [`PendingLedger::rng`], so that the
+    /// same result comes out when the book is validated later.
     fn apply(
         &self,
         ledger: &PendingLedger<Self>,
         state: Self::State,
     ) -> Result<Self::State, Error<Self>>;
-    fn applicable(&self, ledger: &Ledger<Self>, state: &Self::State) -> bool; // Should perhaps return an Option<Error<Self>> instead?
-    fn bit_cost(&self, state: &Self::State) -> usize;
+    /// Checks whether the action can be applied to `state`. If it can't, the
+    /// error should say why, usually with [`Error::bad_action`].
+    fn applicable(&self, ledger: &Ledger<Self>, state: &Self::State) -> Result<(), Error<Self>>;
+    /// How many bits of the hash have to match to add this action after the
+    /// messages in `ledger`, with `state` being the state they lead to. See
+    /// [`difficulty`](super::difficulty) for costs that depend on history.
+    fn bit_cost(&self, ledger: &Ledger<Self>, state: &Self::State) -> usize;
 }
 
-pub trait State: Default {
+/// States are [`Debug`] so that tools like `dit diff` can show them.
+pub trait State: Default + Debug {
     /// Read state from header lines of a file
     fn read_header_line(self, header_line: &str) -> Self;
-    /// A hash that is supposedly unique to the file.
+    /// A hash that is unique to the book, usually read from the `root=` header
+    /// line. The first message of the book is linked to it, so messages can't
+    /// be copied from one book to another.
+    ///
+    /// Books written before root hashes existed use [`HexString::default()`].
     fn root_hash(&self) -> HexString;
     /// What Mode this state is for
     fn mode() -> Mode;
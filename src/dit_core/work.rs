@@ -1,3 +1,9 @@
+use sha3::{Digest, Sha3_224};
+
+/// Keys are never shorter than this, which is also the width of every key
+/// written before keys could be wider.
+const MIN_KEY_BYTES: usize = 4;
+
 const BITS: [u8; 8] = [
     0b0, 0b1, 0b11, 0b111, 0b1111, 0b1_1111, 0b11_1111, 0b111_1111,
 ];
@@ -39,6 +45,28 @@ pub fn bit_match(match_count: usize, left: &[u8], right: &[u8]) -> bool {
     tailend && left[lefti..] == right[righti..]
 }
 
+/// How many bytes a key needs so that there are enough keys to (probably)
+/// find one matching `bit_cost` bits.
+pub fn key_width(bit_cost: usize) -> usize {
+    bit_cost.div_ceil(8).max(MIN_KEY_BYTES)
+}
+
+/// Makes `key` long enough to be matched against `match_count` bits.
+///
+/// Keys that are already long enough are returned unchanged. Shorter keys are
+/// extended at the front with bytes derived from hashing the key, so a high
+/// bit cost following a short key is still compared against bits that come
+/// from the key rather than against padding.
+pub fn stretch_key(key: &[u8], match_count: usize) -> Vec<u8> {
+    let mut stretched = Vec::from(key);
+    let mut digest = Vec::from(key);
+    while stretched.len() * 8 < match_count {
+        digest = Sha3_224::digest(&digest).to_vec();
+        stretched.splice(0..0, digest.iter().cloned());
+    }
+    stretched
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -93,4 +121,27 @@ mod test {
     fn bit_match_matching_no_bytes_are_treated_like_zero() {
         assert!(bit_match(5, &[], &ONE_BYTE))
     }
+
+    #[test]
+    fn key_width_is_at_least_four_bytes() {
+        assert_eq!(key_width(0), 4);
+        assert_eq!(key_width(32), 4);
+        assert_eq!(key_width(33), 5);
+        assert_eq!(key_width(64), 8);
+    }
+
+    #[test]
+    fn stretch_key_leaves_long_enough_keys_alone() {
+        assert_eq!(stretch_key(&BYTES_0, 40), BYTES_0.to_vec());
+        assert_eq!(stretch_key(&BYTES_0[1..], 32), BYTES_0[1..].to_vec());
+    }
+
+    #[test]
+    fn stretch_key_keeps_key_at_the_end() {
+        let stretched = stretch_key(&BYTES_0[1..], 100);
+        assert!(stretched.len() * 8 >= 100);
+        assert!(stretched.ends_with(&BYTES_0[1..]));
+        assert!(bit_match(32, &stretched, &BYTES_0));
+        assert!(!bit_match(100, &stretched, &[0; 13]));
+    }
 }
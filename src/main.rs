@@ -1,4 +1,3 @@
-use dit;
 use dit::app;
 
 fn main() {
@@ -1,5 +1,6 @@
 use super::dit_core::{self, Action, HexString, Ledger, Mode, PendingLedger, State};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub mod spells {
     use serde::{Deserialize, Serialize};
@@ -36,10 +37,19 @@ impl Action for ActionA {
         }
     }
 
-    fn applicable(&self, _ledger: &Ledger<Self>, state: &Self::State) -> bool {
+    fn applicable(
+        &self,
+        _ledger: &Ledger<Self>,
+        state: &Self::State,
+    ) -> Result<(), dit_core::Error<Self>> {
         match self {
-            ActionA::UpdateVersion { version } => state.version() < *version,
-            _ => true,
+            ActionA::UpdateVersion { version } if state.version() >= *version => {
+                Err(dit_core::Error::bad_action(
+                    self.clone(),
+                    format!("the book is already on version {}", state.version()),
+                ))
+            }
+            _ => Ok(()),
         }
     }
 
@@ -58,8 +68,9 @@ impl Action for ActionA {
     /// a parameter, since the state might influence how difficult an action
     /// would be. For instance, a high level wizard might learn spells easier
     /// than a warrior, or you might be able to store mana to cast a spell later
-    /// for cheaper.
-    fn bit_cost(&self, _state: &Self::State) -> usize {
+    /// for cheaper. It takes the ledger too, since history can matter, though
+    /// no action in this mode looks at it.
+    fn bit_cost(&self, _ledger: &Ledger<Self>, _state: &Self::State) -> usize {
         match self {
             ActionA::UpdateVersion { .. } => 1,
             ActionA::AttemptSeekEncounter => 5,
@@ -69,15 +80,17 @@ impl Action for ActionA {
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for ActionA {
     fn default() -> Self {
         ActionA::NoOp
     }
 }
 
-impl ToString for ActionA {
-    fn to_string(&self) -> String {
-        serde_json::to_string(self).expect("All actions should be serializable")
+impl fmt::Display for ActionA {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let json = serde_json::to_string(self).expect("All actions should be serializable");
+        write!(f, "{}", json)
     }
 }
 
@@ -85,6 +98,7 @@ impl ToString for ActionA {
 pub struct StateA {
     version: usize,
     hp: isize,
+    root_hash: HexString,
 }
 
 impl StateA {
@@ -98,20 +112,32 @@ impl StateA {
 
     pub fn update_version(&self, version: usize) -> Result<StateA, dit_core::Error<ActionA>> {
         if self.version > version {
-            Err(dit_core::Error::BadAction)
+            Err(dit_core::Error::bad_action(
+                ActionA::UpdateVersion { version },
+                format!("version {} is older than version {}", version, self.version),
+            ))
         } else {
-            Ok(StateA { version, ..*self })
+            Ok(StateA {
+                version,
+                ..self.clone()
+            })
         }
     }
 }
 
 impl State for StateA {
-    fn read_header_line(self, _header_line: &str) -> Self {
-        self.clone()
+    fn read_header_line(self, header_line: &str) -> Self {
+        match header_line.split_once('=') {
+            Some(("root", root_hash)) => match HexString::parse(root_hash) {
+                Some(root_hash) => StateA { root_hash, ..self },
+                None => self,
+            },
+            _ => self,
+        }
     }
 
     fn root_hash(&self) -> HexString {
-        HexString::default()
+        self.root_hash.clone()
     }
 
     fn mode() -> Mode {
@@ -120,10 +146,12 @@ impl State for StateA {
 }
 
 impl Default for StateA {
+    #[allow(clippy::inconsistent_digit_grouping, clippy::zero_prefixed_literal)]
     fn default() -> StateA {
         StateA {
             version: 0_01_00,
             hp: 100,
+            root_hash: HexString::default(),
         }
     }
 }